@@ -32,11 +32,21 @@ CACHE 1;
 -- ----------------------------
 DROP TABLE IF EXISTS "public"."application_use";
 CREATE TABLE "public"."application_use" (
-  "id" int8 NOT NULL,
+  "id" int8 NOT NULL GENERATED BY DEFAULT AS IDENTITY (
+INCREMENT 1
+MINVALUE  1
+MAXVALUE 9223372036854775807
+START 1
+CACHE 1
+),
   "app_id" varchar(255) COLLATE "pg_catalog"."default",
   "token" varchar(255) COLLATE "pg_catalog"."default",
   "app_auth_url" varchar(1000) COLLATE "pg_catalog"."default",
-  "app_callback_message" varchar(1000) COLLATE "pg_catalog"."default"
+  "app_callback_message" varchar(1000) COLLATE "pg_catalog"."default",
+  "max_sessions_per_user" int4 NOT NULL DEFAULT 0,
+  "session_policy" varchar(32) COLLATE "pg_catalog"."default" NOT NULL DEFAULT 'evict_oldest'::character varying,
+  "auth_mode" varchar(16) COLLATE "pg_catalog"."default" NOT NULL DEFAULT 'http'::character varying,
+  "enabled" bool NOT NULL DEFAULT true
 )
 ;
 COMMENT ON COLUMN "public"."application_use"."id" IS '用户';
@@ -44,11 +54,75 @@ COMMENT ON COLUMN "public"."application_use"."app_id" IS '应用ID';
 COMMENT ON COLUMN "public"."application_use"."token" IS 'toekn';
 COMMENT ON COLUMN "public"."application_use"."app_auth_url" IS '授权ID';
 COMMENT ON COLUMN "public"."application_use"."app_callback_message" IS '消息回调地址';
+COMMENT ON COLUMN "public"."application_use"."max_sessions_per_user" IS '每个用户最大同时在线会话数，0 不限制';
+COMMENT ON COLUMN "public"."application_use"."session_policy" IS '超出会话数时的策略：reject | evict_oldest | per_device';
+COMMENT ON COLUMN "public"."application_use"."auth_mode" IS '连接 token 校验方式：http（调用 app_auth_url）| jwt（token 作为密钥的 HS256 签名令牌）';
+COMMENT ON COLUMN "public"."application_use"."enabled" IS '是否启用，停用后拒绝新连接';
 
 -- ----------------------------
 -- Records of application_use
 -- ----------------------------
-INSERT INTO "public"."application_use" VALUES (1, 'app', '123456', 'http://localhost:7004/app/ws/auth', 'http://localhost:7004/pc28/message');
+INSERT INTO "public"."application_use" VALUES (1, 'app', '123456', 'http://localhost:7004/app/ws/auth', 'http://localhost:7004/pc28/message', 0, 'evict_oldest', 'http', 't');
+
+-- ----------------------------
+-- Table structure for callback_dead_letter
+-- ----------------------------
+DROP TABLE IF EXISTS "public"."callback_dead_letter";
+CREATE TABLE "public"."callback_dead_letter" (
+  "id" int8 NOT NULL GENERATED ALWAYS AS IDENTITY (
+INCREMENT 1
+MINVALUE  1
+MAXVALUE 9223372036854775807
+START 1
+CACHE 1
+),
+  "app_id" varchar(255) COLLATE "pg_catalog"."default",
+  "user_id" varchar(255) COLLATE "pg_catalog"."default",
+  "session_id" varchar(64) COLLATE "pg_catalog"."default",
+  "callback_url" varchar(1000) COLLATE "pg_catalog"."default",
+  "message" text COLLATE "pg_catalog"."default",
+  "attempts" int4 NOT NULL DEFAULT 0,
+  "last_error" text COLLATE "pg_catalog"."default",
+  "create_time" timestamptz NOT NULL DEFAULT now()
+)
+;
+COMMENT ON COLUMN "public"."callback_dead_letter"."app_id" IS '应用ID';
+COMMENT ON COLUMN "public"."callback_dead_letter"."user_id" IS '用户ID';
+COMMENT ON COLUMN "public"."callback_dead_letter"."session_id" IS '会话ID';
+COMMENT ON COLUMN "public"."callback_dead_letter"."callback_url" IS '消息回调地址';
+COMMENT ON COLUMN "public"."callback_dead_letter"."message" IS '消息内容';
+COMMENT ON COLUMN "public"."callback_dead_letter"."attempts" IS '投递次数';
+COMMENT ON COLUMN "public"."callback_dead_letter"."last_error" IS '最后一次失败原因';
+COMMENT ON COLUMN "public"."callback_dead_letter"."create_time" IS '创建时间';
+
+-- ----------------------------
+-- Table structure for offline_message
+-- ----------------------------
+DROP TABLE IF EXISTS "public"."offline_message";
+CREATE TABLE "public"."offline_message" (
+  "id" int8 NOT NULL GENERATED ALWAYS AS IDENTITY (
+INCREMENT 1
+MINVALUE  1
+MAXVALUE 9223372036854775807
+START 1
+CACHE 1
+),
+  "app_id" varchar(255) COLLATE "pg_catalog"."default" NOT NULL,
+  "user_id" varchar(255) COLLATE "pg_catalog"."default" NOT NULL,
+  "message_id" varchar(64) COLLATE "pg_catalog"."default",
+  "message" text COLLATE "pg_catalog"."default" NOT NULL,
+  "binary" bool NOT NULL DEFAULT false,
+  "create_time" timestamptz NOT NULL DEFAULT now(),
+  "expire_time" timestamptz NOT NULL
+)
+;
+COMMENT ON COLUMN "public"."offline_message"."app_id" IS '应用ID';
+COMMENT ON COLUMN "public"."offline_message"."user_id" IS '用户ID';
+COMMENT ON COLUMN "public"."offline_message"."message_id" IS '需要确认的消息ID';
+COMMENT ON COLUMN "public"."offline_message"."message" IS '消息内容';
+COMMENT ON COLUMN "public"."offline_message"."binary" IS '是否为二进制消息（message 为 base64 编码）';
+COMMENT ON COLUMN "public"."offline_message"."create_time" IS '创建时间';
+COMMENT ON COLUMN "public"."offline_message"."expire_time" IS '过期时间';
 
 -- ----------------------------
 -- Table structure for user
@@ -90,12 +164,29 @@ SELECT setval('"public"."user_id_seq"', 5, true);
 -- Primary Key structure for table application_use
 -- ----------------------------
 ALTER TABLE "public"."application_use" ADD CONSTRAINT "applicaton_use_pk" PRIMARY KEY ("id");
+CREATE UNIQUE INDEX "application_use_app_id_idx" ON "public"."application_use" ("app_id");
+
+-- ----------------------------
+-- Auto increment value for application_use
+-- ----------------------------
+SELECT setval('"public"."application_use_id_seq"', 1, true);
 
 -- ----------------------------
 -- Auto increment value for user
 -- ----------------------------
 SELECT setval('"public"."user_id_seq"', 5, true);
 
+-- ----------------------------
+-- Primary Key structure for table callback_dead_letter
+-- ----------------------------
+ALTER TABLE "public"."callback_dead_letter" ADD CONSTRAINT "callback_dead_letter_pk" PRIMARY KEY ("id");
+
+-- ----------------------------
+-- Primary Key structure for table offline_message
+-- ----------------------------
+ALTER TABLE "public"."offline_message" ADD CONSTRAINT "offline_message_pk" PRIMARY KEY ("id");
+CREATE INDEX "offline_message_app_user_idx" ON "public"."offline_message" ("app_id", "user_id", "id");
+
 -- ----------------------------
 -- Primary Key structure for table user
 -- ----------------------------
@@ -0,0 +1,110 @@
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use log::error;
+use std::fmt;
+use crate::common::dto::ResultVo;
+
+/// 接口统一错误，响应体为 ResultVo { code, msg }
+#[derive(Debug)]
+pub enum AppError {
+    // 请求参数错误
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    // 资源已存在
+    Conflict(String),
+    TooManyRequests(String),
+    // 依赖服务（Redis 等）不可用
+    Unavailable(String),
+    // 上游服务（应用认证服务等）出错
+    BadGateway(String),
+    Internal(String),
+}
+
+impl AppError {
+    /// ResultVo 中的业务码，成功为 0
+    pub fn code(&self) -> i32 {
+        1
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::BadRequest(msg)
+            | AppError::Unauthorized(msg)
+            | AppError::Forbidden(msg)
+            | AppError::NotFound(msg)
+            | AppError::Conflict(msg)
+            | AppError::TooManyRequests(msg)
+            | AppError::Unavailable(msg)
+            | AppError::BadGateway(msg)
+            | AppError::Internal(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::BadGateway(_) => StatusCode::BAD_GATEWAY,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(ResultVo::<()>::error(self.code(), self.to_string()))
+    }
+}
+
+// 数据库错误只记录日志，不把连接串等细节返回给调用方
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::RowNotFound => AppError::NotFound("记录不存在".to_string()),
+            e => {
+                error!("Database error: {:?}", e);
+                AppError::Internal("服务器异常".to_string())
+            }
+        }
+    }
+}
+
+impl From<redis::RedisError> for AppError {
+    fn from(e: redis::RedisError) -> Self {
+        error!("Redis error: {:?}", e);
+        AppError::Unavailable("Redis 服务不可用".to_string())
+    }
+}
+
+impl From<password_hash::Error> for AppError {
+    fn from(e: password_hash::Error) -> Self {
+        error!("Password hash error: {:?}", e);
+        AppError::Internal("服务器异常".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+
+    #[actix_web::test]
+    async fn error_response_is_result_vo() {
+        let response = AppError::from(sqlx::Error::RowNotFound).error_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], 1);
+        assert_eq!(body["msg"], "记录不存在");
+        assert!(body["data"].is_null());
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, RwLock};
+use std::time::Instant;
+
+// 延迟直方图的桶（秒）
+const LATENCY_BUCKETS: [f64; 11] = [0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+
+/// 本节点各应用的活跃连接数
+pub static CONNECTIONS_ACTIVE: LazyLock<Gauge> = LazyLock::new(|| {
+    Gauge::new("ws_connections_active", "Active WebSocket connections on this node", &["app_id"])
+});
+pub static CONNECTS_TOTAL: LazyLock<Counter> = LazyLock::new(|| {
+    Counter::new("ws_connects_total", "WebSocket connections accepted", &["app_id"])
+});
+pub static DISCONNECTS_TOTAL: LazyLock<Counter> = LazyLock::new(|| {
+    Counter::new("ws_disconnects_total", "WebSocket connections closed", &["app_id"])
+});
+/// 升级前认证失败次数，reason 见 AuthError::reason
+pub static AUTH_FAILURES_TOTAL: LazyLock<Counter> = LazyLock::new(|| {
+    Counter::new("ws_auth_failures_total", "Rejected WebSocket upgrades", &["reason"])
+});
+/// 客户端发来的数据帧数
+pub static MESSAGES_IN_TOTAL: LazyLock<Counter> = LazyLock::new(|| {
+    Counter::new("ws_messages_in_total", "Data frames received from clients", &["app_id"])
+});
+/// 推送给客户端的消息数
+pub static MESSAGES_OUT_TOTAL: LazyLock<Counter> = LazyLock::new(|| {
+    Counter::new("ws_messages_out_total", "Messages pushed to clients", &["app_id"])
+});
+pub static PUSH_DURATION: LazyLock<Histogram> = LazyLock::new(|| {
+    Histogram::new("ws_push_request_duration_seconds", "Push API latency", &["endpoint"], &LATENCY_BUCKETS)
+});
+/// 转发到其他节点失败次数，peer 为 ip:port
+pub static FORWARD_FAILURES_TOTAL: LazyLock<Counter> = LazyLock::new(|| {
+    Counter::new("ws_forward_failures_total", "Failed forwards to peer nodes", &["peer"])
+});
+pub static REDIS_DURATION: LazyLock<Histogram> = LazyLock::new(|| {
+    Histogram::new("ws_redis_command_duration_seconds", "Redis call latency", &["command"], &LATENCY_BUCKETS)
+});
+
+// 各连接邮箱中尚未处理的下行消息数：session_id -> 积压数，投递消息时只取读锁
+static MAILBOX_BACKLOG: LazyLock<RwLock<HashMap<String, Arc<AtomicI64>>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+// 按标签值取出（或创建）单个序列，已存在时只取读锁
+fn series<T: Default>(values: &RwLock<HashMap<Vec<String>, Arc<T>>>, label_values: &[&str]) -> Arc<T> {
+    let key = key(label_values);
+    if let Some(value) = values.read().unwrap().get(&key) {
+        return value.clone();
+    }
+    values.write().unwrap().entry(key).or_default().clone()
+}
+
+/// 计数器
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    values: RwLock<HashMap<Vec<String>, Arc<AtomicU64>>>,
+}
+
+impl Counter {
+    pub fn new(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+        Self { name, help, labels, values: RwLock::new(HashMap::new()) }
+    }
+
+    pub fn inc(&self, label_values: &[&str]) {
+        self.with(label_values).fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 取出指定标签值的序列，热路径上预先取出并缓存，之后直接原子递增
+    pub fn with(&self, label_values: &[&str]) -> Arc<AtomicU64> {
+        series(&self.values, label_values)
+    }
+
+    fn render(&self, out: &mut String) {
+        header(out, self.name, self.help, "counter");
+        for (values, value) in sorted(&self.values.read().unwrap()) {
+            let _ = writeln!(out, "{}{} {}", self.name, labels(self.labels, &values, None), value.load(Ordering::Relaxed));
+        }
+    }
+}
+
+/// 可增减的指标
+pub struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    values: RwLock<HashMap<Vec<String>, Arc<AtomicI64>>>,
+}
+
+impl Gauge {
+    pub fn new(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+        Self { name, help, labels, values: RwLock::new(HashMap::new()) }
+    }
+
+    pub fn inc(&self, label_values: &[&str]) {
+        self.with(label_values).fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dec(&self, label_values: &[&str]) {
+        self.with(label_values).fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// 取出指定标签值的序列
+    pub fn with(&self, label_values: &[&str]) -> Arc<AtomicI64> {
+        series(&self.values, label_values)
+    }
+
+    fn render(&self, out: &mut String) {
+        header(out, self.name, self.help, "gauge");
+        for (values, value) in sorted(&self.values.read().unwrap()) {
+            let _ = writeln!(out, "{}{} {}", self.name, labels(self.labels, &values, None), value.load(Ordering::Relaxed));
+        }
+    }
+}
+
+#[derive(Default)]
+struct HistogramData {
+    // 每个桶的计数（不累加），最后一个为 +Inf
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+/// 直方图
+pub struct Histogram {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    bounds: &'static [f64],
+    // 每个序列单独加锁，不同标签值的记录互不阻塞
+    values: RwLock<HashMap<Vec<String>, Arc<Mutex<HistogramData>>>>,
+}
+
+impl Histogram {
+    pub fn new(
+        name: &'static str,
+        help: &'static str,
+        labels: &'static [&'static str],
+        bounds: &'static [f64],
+    ) -> Self {
+        Self { name, help, labels, bounds, values: RwLock::new(HashMap::new()) }
+    }
+
+    #[cfg(test)]
+    fn observe(&self, label_values: &[&str], value: f64) {
+        self.record(&series(&self.values, label_values), value);
+    }
+
+    fn record(&self, series: &Mutex<HistogramData>, value: f64) {
+        let mut data = series.lock().unwrap();
+        if data.buckets.is_empty() {
+            data.buckets = vec![0; self.bounds.len() + 1];
+        }
+        let index = self.bounds.iter().position(|b| value <= *b).unwrap_or(self.bounds.len());
+        data.buckets[index] += 1;
+        data.sum += value;
+        data.count += 1;
+    }
+
+    /// 计时，返回值 drop 时记录耗时
+    pub fn start_timer(&'static self, label_values: &[&str]) -> HistogramTimer {
+        HistogramTimer {
+            histogram: self,
+            series: series(&self.values, label_values),
+            start: Instant::now(),
+        }
+    }
+
+    fn render(&self, out: &mut String) {
+        header(out, self.name, self.help, "histogram");
+        for (values, data) in sorted(&self.values.read().unwrap()) {
+            let data = data.lock().unwrap();
+            let mut cumulative = 0;
+            for (i, count) in data.buckets.iter().enumerate() {
+                cumulative += count;
+                let le = self.bounds.get(i).map_or("+Inf".to_string(), |b| b.to_string());
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{} {}",
+                    self.name,
+                    labels(self.labels, &values, Some(&le)),
+                    cumulative
+                );
+            }
+            let label_text = labels(self.labels, &values, None);
+            let _ = writeln!(out, "{}_sum{} {}", self.name, label_text, data.sum);
+            let _ = writeln!(out, "{}_count{} {}", self.name, label_text, data.count);
+        }
+    }
+}
+
+pub struct HistogramTimer {
+    histogram: &'static Histogram,
+    series: Arc<Mutex<HistogramData>>,
+    start: Instant,
+}
+
+impl Drop for HistogramTimer {
+    fn drop(&mut self) {
+        self.histogram.record(&self.series, self.start.elapsed().as_secs_f64());
+    }
+}
+
+/// 连接建立时登记邮箱积压计数，返回的计数由连接在处理消息后递减
+pub fn mailbox_register(session_id: &str) -> Arc<AtomicI64> {
+    let backlog = Arc::new(AtomicI64::new(0));
+    MAILBOX_BACKLOG
+        .write()
+        .unwrap()
+        .insert(session_id.to_string(), backlog.clone());
+    backlog
+}
+
+pub fn mailbox_unregister(session_id: &str) {
+    MAILBOX_BACKLOG.write().unwrap().remove(session_id);
+}
+
+/// 向连接邮箱投递一条下行消息
+pub fn mailbox_enqueued(session_id: &str) {
+    if let Some(backlog) = MAILBOX_BACKLOG.read().unwrap().get(session_id) {
+        backlog.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Prometheus 文本格式
+pub fn render() -> String {
+    let mut out = String::new();
+    CONNECTIONS_ACTIVE.render(&mut out);
+    CONNECTS_TOTAL.render(&mut out);
+    DISCONNECTS_TOTAL.render(&mut out);
+    AUTH_FAILURES_TOTAL.render(&mut out);
+    MESSAGES_IN_TOTAL.render(&mut out);
+    MESSAGES_OUT_TOTAL.render(&mut out);
+    PUSH_DURATION.render(&mut out);
+    FORWARD_FAILURES_TOTAL.render(&mut out);
+    REDIS_DURATION.render(&mut out);
+
+    // 按连接的积压数汇总，避免以 session_id 作为标签
+    let (max, total) = MAILBOX_BACKLOG
+        .read()
+        .unwrap()
+        .values()
+        .map(|backlog| backlog.load(Ordering::Relaxed).max(0))
+        .fold((0, 0), |(max, total), n| (max.max(n), total + n));
+    header(&mut out, "ws_mailbox_backlog_max", "Largest mailbox backlog of a single connection", "gauge");
+    let _ = writeln!(out, "ws_mailbox_backlog_max {}", max);
+    header(&mut out, "ws_mailbox_backlog_total", "Queued outbound messages across all connections", "gauge");
+    let _ = writeln!(out, "ws_mailbox_backlog_total {}", total);
+    out
+}
+
+fn key(label_values: &[&str]) -> Vec<String> {
+    label_values.iter().map(|v| v.to_string()).collect()
+}
+
+fn sorted<T: Clone>(values: &HashMap<Vec<String>, T>) -> Vec<(Vec<String>, T)> {
+    let mut list: Vec<_> = values.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+    list.sort_by(|a, b| a.0.cmp(&b.0));
+    list
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn labels(names: &[&str], values: &[String], le: Option<&str>) -> String {
+    let mut pairs: Vec<String> = names
+        .iter()
+        .zip(values)
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+        .collect();
+    if let Some(le) = le {
+        pairs.push(format!("le=\"{}\"", le));
+    }
+    if pairs.is_empty() {
+        return String::new();
+    }
+    format!("{{{}}}", pairs.join(","))
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_counter_and_histogram() {
+        let counter = Counter::new("test_total", "Test", &["app_id"]);
+        counter.inc(&["a\"b"]);
+        // 预先取出的序列与按标签递增的是同一个计数
+        counter.with(&["a\"b"]).fetch_add(1, Ordering::Relaxed);
+        let mut out = String::new();
+        counter.render(&mut out);
+        assert!(out.contains("# TYPE test_total counter"));
+        assert!(out.contains("test_total{app_id=\"a\\\"b\"} 2"));
+
+        let histogram = Histogram::new("test_seconds", "Test", &[], &[0.1, 1.0]);
+        histogram.observe(&[], 0.05);
+        histogram.observe(&[], 0.5);
+        histogram.observe(&[], 5.0);
+        let mut out = String::new();
+        histogram.render(&mut out);
+        assert!(out.contains("test_seconds_bucket{le=\"0.1\"} 1"));
+        assert!(out.contains("test_seconds_bucket{le=\"1\"} 2"));
+        assert!(out.contains("test_seconds_bucket{le=\"+Inf\"} 3"));
+        assert!(out.contains("test_seconds_count 3"));
+    }
+}
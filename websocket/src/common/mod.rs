@@ -1 +1,4 @@
-pub mod dto;
\ No newline at end of file
+pub mod dto;
+pub mod metrics;
+pub mod logging;
+pub mod error;
\ No newline at end of file
@@ -1,13 +1,16 @@
 use actix_web::{
     dev::{ServiceRequest, ServiceResponse, Transform},
-    Error, web
+    http::header::{HeaderName, HeaderValue},
+    Error, HttpMessage, web
 };
 use futures::future::{ok, Ready, LocalBoxFuture};
 use std::task::{Context, Poll};
 use std::rc::Rc;
-use actix_web::web::put;
+use crate::common::error::AppError;
 use crate::config::redis_manager::RedisManager;
-use crate::props::config::get_config;
+use crate::web_socket::web_socket_server::AppState;
+use tracing::{info_span, Instrument};
+use uuid::Uuid;
 
 /// 自定义中间件
 pub struct AuthMiddleware;
@@ -50,21 +53,24 @@ where
     fn call(&self, req: ServiceRequest) -> Self::Future {
         let svc = self.service.clone();
 
-        let ignore_paths = vec!["/ws","/api/login","/api/node/push"];
+        let ignore_paths = ["/ws","/api/login","/api/node/push","/healthz","/readyz"];
 
         Box::pin(async move {
             let path = req.path();
 
             if path == "/api/node/push" {
-                let config = get_config().unwrap();
+                let Some(state) = req.app_data::<web::Data<AppState>>() else {
+                    return Err(AppError::Internal("AppState not configured".to_string()).into());
+                };
+                let config = state.config.current();
 
                 if let Some(loc_to_token_header) = req.headers().get("loc_to_token") {
                     let loc_to_token = loc_to_token_header.to_str().unwrap_or("").to_string();
                     if config.node_token != loc_to_token {
-                        return Err(actix_web::error::ErrorUnauthorized("Invalid node token"));
+                        return Err(AppError::Unauthorized("Invalid node token".to_string()).into());
                     }
                 } else {
-                    return Err(actix_web::error::ErrorUnauthorized("Missing node token"));
+                    return Err(AppError::Unauthorized("Missing node token".to_string()).into());
                 }
             }
 
@@ -90,25 +96,104 @@ where
                 if let Some(app_data) = req.app_data::<web::Data<RedisManager>>() {
                     let redis_manager = app_data.as_ref();
 
-                    // redis 查询是否有token
-                    match redis_manager.get(&*("user_token:".to_owned() + &token)) {
-                        Ok(_) => {
+                    // redis 查询是否有token，Redis 不可用时返回 503 而不是 401
+                    match redis_manager.async_exists(&("user_token:".to_owned() + &token)).await {
+                        Ok(true) => {
                             // token 存在，继续请求
                             svc.call(req).await
                         },
-                        Err(_) => {
+                        Ok(false) => {
                             // token 不存在，返回 401
-                            Err(actix_web::error::ErrorUnauthorized("Invalid token"))
+                            Err(AppError::Unauthorized("Invalid token".to_string()).into())
                         }
+                        Err(e) => Err(AppError::from(e).into()),
                     }
                 } else {
                     // Redis连接不可用，返回错误
-                    Err(actix_web::error::ErrorInternalServerError("Redis connection not available"))
+                    Err(AppError::Internal("Redis connection not available".to_string()).into())
                 }
             } else {
                 // 没有 token
-                Err(actix_web::error::ErrorUnauthorized("Missing token"))
+                Err(AppError::Unauthorized("Missing token".to_string()).into())
             }
         })
     }
-}
\ No newline at end of file
+}
+/// 请求ID头，转发到其他节点时原样带上
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// 当前请求的 ID，处理函数可通过 web::ReqData<RequestId> 获取
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+/// 为每个请求分配 request_id（沿用上游传入的 x-request-id），并在 request span 中处理请求
+pub struct RequestIdMiddleware;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestIdMiddleware
+where
+    S: actix_web::dev::Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestIdMiddlewareMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RequestIdMiddlewareMiddleware {
+            service: Rc::new(service),
+        })
+    }
+}
+
+pub struct RequestIdMiddlewareMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> actix_web::dev::Service<ServiceRequest> for RequestIdMiddlewareMiddleware<S>
+where
+    S: actix_web::dev::Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        // 只接受长度合理的字母数字 ID，其余重新生成
+        let request_id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .filter(|id| {
+                !id.is_empty()
+                    && id.len() <= 64
+                    && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            })
+            .map(str::to_string)
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        req.extensions_mut().insert(RequestId(request_id.clone()));
+
+        let span = info_span!("request", request_id = %request_id, method = %req.method(), path = %req.path());
+        let fut = {
+            let _enter = span.enter();
+            self.service.call(req)
+        };
+
+        Box::pin(
+            async move {
+                let mut res = fut.await?;
+                if let Ok(value) = HeaderValue::from_str(&request_id) {
+                    res.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+                }
+                Ok(res)
+            }
+            .instrument(span),
+        )
+    }
+}
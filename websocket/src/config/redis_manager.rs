@@ -1,137 +1,281 @@
-use redis::{AsyncCommands, Client, Commands, FromRedisValue, RedisResult, ToRedisArgs};
-use redis::aio::{MultiplexedConnection, ConnectionManager};
+use crate::common::metrics;
+use redis::{AsyncCommands, Client, FromRedisValue, RedisResult, Script};
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
-use crate::web_socket::app_node::SessionUser;
+use tokio::sync::OnceCell;
 
+// 建立连接与等待响应的超时时间
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(3);
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(3);
+// 断线重连的最大退避间隔
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(2);
+// 单个 pipeline 的最大命令数
+const PIPELINE_BATCH: usize = 500;
+
+/// Redis 访问入口，所有命令共用一个自动重连的多路复用连接
 #[derive(Clone)]
 pub struct RedisManager {
     client: Arc<Client>,
+    // 首次使用时建立，克隆开销很小，并发命令在同一 TCP 连接上排队
+    manager: Arc<OnceCell<ConnectionManager>>,
 }
 
 impl RedisManager {
-    /// 创建新的 Redis 管理器
+    /// 创建新的 Redis 管理器，连接在第一次执行命令时建立
     pub fn new(url: &str) -> RedisResult<Self> {
         let client = Client::open(url)?;
         Ok(Self {
             client: Arc::new(client),
+            manager: Arc::new(OnceCell::new()),
         })
     }
 
-    /// 创建带超时配置的 Redis 管理器
-    pub fn new_with_timeout(url: &str, timeout: Duration) -> RedisResult<Self> {
-        let client = Client::open(url)?;
-        // 这里可以设置客户端超时配置（如果需要）
-        Ok(Self {
-            client: Arc::new(client),
-        })
+    /// 获取共享的连接管理器，断线后自动重连
+    pub async fn get_connection_manager(&self) -> RedisResult<ConnectionManager> {
+        let manager = self
+            .manager
+            .get_or_try_init(|| async {
+                let config = ConnectionManagerConfig::new()
+                    .set_connection_timeout(Some(CONNECTION_TIMEOUT))
+                    .set_response_timeout(Some(RESPONSE_TIMEOUT))
+                    .set_max_delay(RECONNECT_MAX_DELAY);
+                self.client.get_connection_manager_with_config(config).await
+            })
+            .await?;
+        Ok(manager.clone())
     }
 
-    // ========== 同步方法 ==========
+    /// 异步设置键值
+    pub async fn async_set(&self, key: &str, value: &str) -> RedisResult<()> {
+        let _timer = metrics::REDIS_DURATION.start_timer(&["set"]);
+        let mut conn = self.get_connection_manager().await?;
+        conn.set(key, value).await
+    }
 
-    /// 获取同步连接
-    pub fn get_connection(&self) -> RedisResult<redis::Connection> {
-        self.client.get_connection()
+    /// 异步设置键值带过期时间
+    pub async fn async_set_ex(&self, key: &str, value: &str, seconds: u64) -> RedisResult<()> {
+        let _timer = metrics::REDIS_DURATION.start_timer(&["setex"]);
+        let mut conn = self.get_connection_manager().await?;
+        conn.set_ex(key, value, seconds).await
     }
 
-    /// 设置键值（同步）
-    pub fn set(&self, key: &str, value: &str) -> RedisResult<()> {
-        let mut conn = self.get_connection()?;
-        conn.set(key, value)
+
+    /// 异步获取值，键不存在时返回空字符串
+    pub async fn async_get_not_null(&self, key: &str) -> RedisResult<String> {
+        let _timer = metrics::REDIS_DURATION.start_timer(&["get"]);
+        let mut conn = self.get_connection_manager().await?;
+        let val: Option<String> = conn.get(key).await?;
+        Ok(val.unwrap_or_default())
     }
 
-    /// 设置键值带过期时间（同步）
-    pub fn set_ex(&self, key: &str, value: &str, seconds: u64) -> RedisResult<()> {
-        let mut conn = self.get_connection()?;
-        conn.set_ex(key, value, seconds)
+    /// 批量获取多个键的值（MGET，分批），键不存在时为 None，结果与 keys 顺序一致
+    pub async fn async_mget(&self, keys: &[String]) -> RedisResult<Vec<Option<String>>> {
+        if keys.is_empty() {
+            return Ok(vec![]);
+        }
+        let _timer = metrics::REDIS_DURATION.start_timer(&["mget"]);
+        let mut conn = self.get_connection_manager().await?;
+        let mut results = Vec::with_capacity(keys.len());
+        for chunk in keys.chunks(PIPELINE_BATCH) {
+            let batch: Vec<Option<String>> = redis::cmd("MGET").arg(chunk).query_async(&mut conn).await?;
+            results.extend(batch);
+        }
+        Ok(results)
     }
 
-    /// 获取值（同步）
-    pub fn get(&self, key: &str) -> RedisResult<String> {
-        let mut conn = self.get_connection()?;
-        conn.get(key)
+    /// 异步删除键
+    pub async fn async_del(&self, key: &str) -> RedisResult<()> {
+        let _timer = metrics::REDIS_DURATION.start_timer(&["del"]);
+        let mut conn = self.get_connection_manager().await?;
+        conn.del(key).await
     }
 
-    pub fn get_not_null(&self, key: &str) -> RedisResult<String> {
-        let mut conn = self.get_connection()?;
-        let val: Option<String> = conn.get(key)?;
-        Ok(val.unwrap_or_default())
+    /// 异步检查键是否存在
+    pub async fn async_exists(&self, key: &str) -> RedisResult<bool> {
+        let _timer = metrics::REDIS_DURATION.start_timer(&["exists"]);
+        let mut conn = self.get_connection_manager().await?;
+        conn.exists(key).await
     }
 
-    /// 删除键（同步）
-    pub fn del(&self, key: &str) -> RedisResult<()> {
-        let mut conn = self.get_connection()?;
-        conn.del(key)
+
+    /// 异步向集合添加成员
+    pub async fn async_sadd(&self, key: &str, member: &str) -> RedisResult<()> {
+        let _timer = metrics::REDIS_DURATION.start_timer(&["sadd"]);
+        let mut conn = self.get_connection_manager().await?;
+        conn.sadd(key, member).await
     }
 
-    /// 检查键是否存在（同步）
-    pub fn exists(&self, key: &str) -> RedisResult<bool> {
-        let mut conn = self.get_connection()?;
-        conn.exists(key)
+    /// 异步从集合删除成员
+    pub async fn async_srem(&self, key: &str, member: &str) -> RedisResult<()> {
+        let _timer = metrics::REDIS_DURATION.start_timer(&["srem"]);
+        let mut conn = self.get_connection_manager().await?;
+        conn.srem(key, member).await
     }
 
-    /// 设置过期时间（同步）
-    pub fn expire(&self, key: &str, seconds: i64) -> RedisResult<()> {
-        let mut conn = self.get_connection()?;
-        conn.expire(key, seconds)
+    /// 异步判断成员是否在集合中
+    pub async fn async_sismember(&self, key: &str, member: &str) -> RedisResult<bool> {
+        let _timer = metrics::REDIS_DURATION.start_timer(&["sismember"]);
+        let mut conn = self.get_connection_manager().await?;
+        conn.sismember(key, member).await
     }
 
-    // ========== 异步方法 ==========
+    /// 用 members 整体替换集合（MULTI 事务），members 为空时删除集合
+    pub async fn async_replace_set(&self, key: &str, members: &[String]) -> RedisResult<()> {
+        let _timer = metrics::REDIS_DURATION.start_timer(&["replace_set"]);
+        let mut conn = self.get_connection_manager().await?;
+        let mut pipe = redis::pipe();
+        pipe.atomic().del(key).ignore();
+        if !members.is_empty() {
+            pipe.sadd(key, members).ignore();
+        }
+        pipe.query_async(&mut conn).await
+    }
 
-    /// 获取异步多路复用连接（用于多个并发操作）
-    pub async fn get_multiplexed_connection(&self) -> RedisResult<MultiplexedConnection> {
-        self.client.get_multiplexed_async_connection().await
+    /// 异步获取集合全部成员
+    pub async fn async_smembers(&self, key: &str) -> RedisResult<Vec<String>> {
+        let _timer = metrics::REDIS_DURATION.start_timer(&["smembers"]);
+        let mut conn = self.get_connection_manager().await?;
+        conn.smembers(key).await
     }
 
-    /// 获取异步连接管理器（单个连接）
-    pub async fn get_connection_manager(&self) -> RedisResult<ConnectionManager> {
-        self.client
-            .as_ref()
-            .get_connection_manager()
+    /// 异步设置哈希字段（测试构造数据用，业务代码写入时都带过期时间）
+    #[cfg(test)]
+    pub async fn async_hset(&self, key: &str, field: &str, value: &str) -> RedisResult<()> {
+        let _timer = metrics::REDIS_DURATION.start_timer(&["hset"]);
+        let mut conn = self.get_connection_manager().await?;
+        conn.hset(key, field, value).await
+    }
+
+    /// 设置哈希字段并刷新整个 key 的过期时间（MULTI 事务，一次往返）
+    pub async fn async_hset_expire(&self, key: &str, field: &str, value: &str, seconds: i64) -> RedisResult<()> {
+        let _timer = metrics::REDIS_DURATION.start_timer(&["hset_expire"]);
+        let mut conn = self.get_connection_manager().await?;
+        redis::pipe()
+            .atomic()
+            .hset(key, field, value)
+            .ignore()
+            .expire(key, seconds)
+            .ignore()
+            .query_async(&mut conn)
             .await
     }
 
-    /// 异步设置键值
-    pub async fn async_set(&self, key: &str, value: &str) -> RedisResult<()> {
-        let mut conn = self.get_multiplexed_connection().await?;
-        conn.set(key, value).await
+    /// 异步删除哈希字段
+    pub async fn async_hdel(&self, key: &str, field: &str) -> RedisResult<()> {
+        let _timer = metrics::REDIS_DURATION.start_timer(&["hdel"]);
+        let mut conn = self.get_connection_manager().await?;
+        conn.hdel(key, field).await
     }
 
-    /// 异步设置键值带过期时间
-    pub async fn async_set_ex(&self, key: &str, value: &str, seconds: u64) -> RedisResult<()> {
-        let mut conn = self.get_multiplexed_connection().await?;
-        conn.set_ex(key, value, seconds).await
+    /// 异步获取哈希全部字段
+    pub async fn async_hgetall(&self, key: &str) -> RedisResult<HashMap<String, String>> {
+        let _timer = metrics::REDIS_DURATION.start_timer(&["hgetall"]);
+        let mut conn = self.get_connection_manager().await?;
+        conn.hgetall(key).await
     }
 
-    /// 异步获取值
-    pub async fn async_get(&self, key: &str) -> RedisResult<String> {
-        let mut conn = self.get_multiplexed_connection().await?;
-        conn.get(key).await
+    /// 批量获取多个哈希的全部字段（pipeline），结果与 keys 顺序一致
+    pub async fn async_hgetall_many(&self, keys: &[String]) -> RedisResult<Vec<HashMap<String, String>>> {
+        if keys.is_empty() {
+            return Ok(vec![]);
+        }
+        let _timer = metrics::REDIS_DURATION.start_timer(&["hgetall_pipeline"]);
+        let mut conn = self.get_connection_manager().await?;
+        let mut results = Vec::with_capacity(keys.len());
+        // 分批发送，避免单个 pipeline 过大
+        for chunk in keys.chunks(PIPELINE_BATCH) {
+            let mut pipe = redis::pipe();
+            for key in chunk {
+                pipe.hgetall(key);
+            }
+            let batch: Vec<HashMap<String, String>> = pipe.query_async(&mut conn).await?;
+            results.extend(batch);
+        }
+        Ok(results)
     }
 
-    /// 异步删除键
-    pub async fn async_del(&self, key: &str) -> RedisResult<()> {
-        let mut conn = self.get_multiplexed_connection().await?;
-        conn.del(key).await
+    /// 异步向有序集合添加成员，成员已存在时更新分数
+    pub async fn async_zadd(&self, key: &str, member: &str, score: u64) -> RedisResult<()> {
+        let _timer = metrics::REDIS_DURATION.start_timer(&["zadd"]);
+        let mut conn = self.get_connection_manager().await?;
+        conn.zadd(key, member, score).await
     }
 
-    /// 异步检查键是否存在
-    pub async fn async_exists(&self, key: &str) -> RedisResult<bool> {
-        let mut conn = self.get_multiplexed_connection().await?;
-        conn.exists(key).await
+    /// 异步获取有序集合全部成员
+    pub async fn async_zmembers(&self, key: &str) -> RedisResult<Vec<String>> {
+        let _timer = metrics::REDIS_DURATION.start_timer(&["zrange"]);
+        let mut conn = self.get_connection_manager().await?;
+        conn.zrange(key, 0, -1).await
+    }
+
+    /// 异步删除有序集合中分数不大于 max 的成员，返回删除数
+    pub async fn async_zrem_below(&self, key: &str, max: u64) -> RedisResult<usize> {
+        let _timer = metrics::REDIS_DURATION.start_timer(&["zremrangebyscore"]);
+        let mut conn = self.get_connection_manager().await?;
+        conn.zrembyscore(key, "-inf", max).await
+    }
+
+    /// 批量统计多个有序集合中分数在 [min, max] 内的成员数（pipeline），结果与 keys 顺序一致
+    pub async fn async_zcount_many(&self, keys: &[String], min: &str, max: &str) -> RedisResult<Vec<usize>> {
+        if keys.is_empty() {
+            return Ok(vec![]);
+        }
+        let _timer = metrics::REDIS_DURATION.start_timer(&["zcount_pipeline"]);
+        let mut conn = self.get_connection_manager().await?;
+        let mut results = Vec::with_capacity(keys.len());
+        for chunk in keys.chunks(PIPELINE_BATCH) {
+            let mut pipe = redis::pipe();
+            for key in chunk {
+                pipe.zcount(key, min, max);
+            }
+            let batch: Vec<usize> = pipe.query_async(&mut conn).await?;
+            results.extend(batch);
+        }
+        Ok(results)
     }
 
-    /// 异步设置过期时间
-    pub async fn async_expire(&self, key: &str, seconds: i64) -> RedisResult<()> {
-        let mut conn = self.get_multiplexed_connection().await?;
-        conn.expire(key, seconds).await
+
+    /// 单次 SCAN，返回下一个游标（0 表示遍历结束）与本批 key，count 为每批数量的提示值
+    pub async fn async_scan_page(&self, pattern: &str, cursor: u64, count: usize) -> RedisResult<(u64, Vec<String>)> {
+        let _timer = metrics::REDIS_DURATION.start_timer(&["scan"]);
+        let mut conn = self.get_connection_manager().await?;
+        redis::cmd("SCAN")
+            .arg(cursor)
+            .arg("MATCH")
+            .arg(pattern)
+            .arg("COUNT")
+            .arg(count)
+            .query_async(&mut conn)
+            .await
     }
 
     // ========== 高级功能 ==========
 
-    /// 发布消息到频道
-    pub async fn publish(&self, channel: &str, message: &str) -> RedisResult<()> {
-        let mut conn = self.get_multiplexed_connection().await?;
+    /// 执行 Lua 脚本（原子操作）
+    pub async fn eval_script<T: FromRedisValue>(
+        &self,
+        script: &Script,
+        keys: &[&str],
+        args: &[&str],
+    ) -> RedisResult<T> {
+        let _timer = metrics::REDIS_DURATION.start_timer(&["evalsha"]);
+        let mut conn = self.get_connection_manager().await?;
+        let mut invocation = script.prepare_invoke();
+        for key in keys {
+            invocation.key(*key);
+        }
+        for arg in args {
+            invocation.arg(*arg);
+        }
+        invocation.invoke_async(&mut conn).await
+    }
+
+    /// 发布消息到频道，返回收到消息的订阅者数
+    pub async fn publish(&self, channel: &str, message: &str) -> RedisResult<usize> {
+        let _timer = metrics::REDIS_DURATION.start_timer(&["publish"]);
+        let mut conn = self.get_connection_manager().await?;
         conn.publish(channel, message).await
     }
 
@@ -147,14 +291,10 @@ impl RedisManager {
         Ok(pubsub)
     }
 
-    /// 获取 Redis 客户端（用于特殊操作）
-    pub fn get_client(&self) -> Arc<Client> {
-        self.client.clone()
-    }
-
     /// 测试连接
     pub async fn ping(&self) -> RedisResult<String> {
-        let mut conn = self.get_multiplexed_connection().await?;
+        let _timer = metrics::REDIS_DURATION.start_timer(&["ping"]);
+        let mut conn = self.get_connection_manager().await?;
         redis::cmd("PING").query_async(&mut conn).await
     }
 }
\ No newline at end of file
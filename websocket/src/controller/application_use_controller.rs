@@ -0,0 +1,56 @@
+use actix_web::{delete, get, post, put, web, HttpResponse};
+use actix_web::web::Data;
+use serde_json::json;
+use crate::common::dto::ResultVo;
+use crate::common::error::AppError;
+use crate::domain::application_use::{
+    ApplicationUseCreate, ApplicationUseQuery, ApplicationUseStatus, ApplicationUseUpdate,
+};
+use crate::service::application_use_service;
+use crate::web_socket::web_socket_server::AppState;
+
+#[get("/api/app/page")]
+pub async fn get_page(query: web::Query<ApplicationUseQuery>, state: Data<AppState>) -> Result<HttpResponse, AppError> {
+    let apps = application_use_service::get_page(&state.db, &state.redis, query.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(json!(ResultVo::ok_with(apps))))
+}
+
+#[get("/api/app/{app_id}")]
+pub async fn get_app(app_id: web::Path<String>, state: Data<AppState>) -> Result<HttpResponse, AppError> {
+    let app = application_use_service::get_app(&state.db, &state.redis, &app_id).await?;
+    Ok(HttpResponse::Ok().json(json!(ResultVo::ok_with(app))))
+}
+
+#[post("/api/app")]
+pub async fn create_app(app_create: web::Json<ApplicationUseCreate>, state: Data<AppState>) -> Result<HttpResponse, AppError> {
+    let app = application_use_service::create_app(&state.db, app_create.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(json!(ResultVo::ok_with(app))))
+}
+
+#[put("/api/app")]
+pub async fn update_app(app_update: web::Json<ApplicationUseUpdate>, state: Data<AppState>) -> Result<HttpResponse, AppError> {
+    let number = application_use_service::update_app(&state.db, &state.redis, app_update.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(json!(ResultVo::ok_with(number))))
+}
+
+#[put("/api/app/{app_id}/token")]
+pub async fn rotate_token(app_id: web::Path<String>, state: Data<AppState>) -> Result<HttpResponse, AppError> {
+    let token = application_use_service::rotate_token(&state.db, &state.redis, &app_id).await?;
+    Ok(HttpResponse::Ok().json(json!(ResultVo::ok_with(token))))
+}
+
+#[put("/api/app/{app_id}/status")]
+pub async fn update_status(
+    app_id: web::Path<String>,
+    status: web::Json<ApplicationUseStatus>,
+    state: Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let number = application_use_service::set_enabled(&state.db, &state.redis, &app_id, status.enabled).await?;
+    Ok(HttpResponse::Ok().json(json!(ResultVo::ok_with(number))))
+}
+
+#[delete("/api/app/{app_id}")]
+pub async fn delete_app(app_id: web::Path<String>, state: Data<AppState>) -> Result<HttpResponse, AppError> {
+    let number = application_use_service::delete_app(&state.db, &state.redis, &app_id).await?;
+    Ok(HttpResponse::Ok().json(json!(ResultVo::ok_with(number))))
+}
@@ -0,0 +1,88 @@
+use actix_web::{delete, get, put, web::Data, HttpResponse};
+use log::{info, warn};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use crate::common::dto::ResultVo;
+use crate::config::redis_manager::RedisManager;
+use crate::web_socket::node_registry;
+use crate::web_socket::web_socket_server::AppState;
+
+// 单项依赖检查的超时时间，避免探针被挂起的连接拖住
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+// 存活探针：进程能响应即可，不检查依赖
+#[get("/healthz")]
+pub async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().json(json!(ResultVo::ok_with("ok")))
+}
+
+// 就绪探针：Postgres、两个 Redis 客户端可用且本节点已注册；排空模式下返回 503
+#[get("/readyz")]
+pub async fn readyz(state: Data<AppState>, redis: Data<RedisManager>) -> HttpResponse {
+    let config = state.config.current();
+    let (db, redis_ws, redis_url, node) = futures::join!(
+        check(async { sqlx::query("select 1").execute(&state.db).await.map(|_| ()).map_err(|e| e.to_string()) }),
+        check(async { state.redis.ping().await.map(|_| ()).map_err(|e| e.to_string()) }),
+        check(async { redis.ping().await.map(|_| ()).map_err(|e| e.to_string()) }),
+        check(async {
+            match node_registry::is_alive(&state.redis, &config.app_ip, config.port).await {
+                Ok(true) => Ok(()),
+                Ok(false) => Err("node not registered".to_string()),
+                Err(e) => Err(e.to_string()),
+            }
+        }),
+    );
+    let draining = state.draining.load(Ordering::Relaxed);
+
+    let mut checks = BTreeMap::new();
+    checks.insert("postgres", db);
+    checks.insert("redis_ws", redis_ws);
+    checks.insert("redis", redis_url);
+    checks.insert("node_registered", node);
+    checks.insert("draining", if draining { Err("node is draining".to_string()) } else { Ok(()) });
+
+    let failed: Vec<&str> = checks.iter().filter(|(_, r)| r.is_err()).map(|(name, _)| *name).collect();
+    let detail: BTreeMap<&str, String> = checks
+        .into_iter()
+        .map(|(name, r)| (name, r.err().unwrap_or_else(|| "ok".to_string())))
+        .collect();
+
+    if failed.is_empty() {
+        return HttpResponse::Ok().json(json!(ResultVo::ok_with(detail)));
+    }
+    if !draining {
+        warn!("Readiness check failed: {:?}", failed);
+    }
+    HttpResponse::ServiceUnavailable().json(json!(ResultVo {
+        code: 1,
+        msg: format!("not ready: {}", failed.join(", ")),
+        data: Some(detail),
+    }))
+}
+
+// 进入排空模式：就绪探针失败，负载均衡摘除本节点，已建立的连接保持不变
+#[put("/api/node/drain")]
+pub async fn start_drain(state: Data<AppState>) -> HttpResponse {
+    if !state.draining.swap(true, Ordering::Relaxed) {
+        info!("Node entering drain mode");
+    }
+    HttpResponse::Ok().json(json!(ResultVo::ok_with(json!({ "draining": true }))))
+}
+
+// 退出排空模式
+#[delete("/api/node/drain")]
+pub async fn stop_drain(state: Data<AppState>) -> HttpResponse {
+    if state.draining.swap(false, Ordering::Relaxed) {
+        info!("Node leaving drain mode");
+    }
+    HttpResponse::Ok().json(json!(ResultVo::ok_with(json!({ "draining": false }))))
+}
+
+async fn check(fut: impl Future<Output = Result<(), String>>) -> Result<(), String> {
+    tokio::time::timeout(CHECK_TIMEOUT, fut)
+        .await
+        .unwrap_or_else(|_| Err("timeout".to_string()))
+}
@@ -1,69 +1,166 @@
-use actix::spawn;
-use crate::web_socket::web_socket_server::{AppState, PushRequest, ServerText, SessionManager};
+use tracing::Instrument;
+use crate::web_socket::web_socket_server::{AppState, ServerText, SessionManager};
 use actix_web::{
     HttpResponse, post,
-    web::{self, Data},
+    web::{self, Bytes, Data},
 };
-use crate::http::http_util::http_post;
-use crate::props::config::{get_config, Config};
-use crate::vo::message_vo::{MessageVO, NodeMessageVO, NodeTo, NodeToVo};
+use crate::http::http_util::HttpClient;
+use crate::config::middleware::{RequestId, REQUEST_ID_HEADER};
+use crate::config::redis_manager::RedisManager;
+use crate::props::config::{Config, NodeRoute};
+use crate::common::dto::ResultVo;
+use crate::common::error::AppError;
+use crate::common::metrics;
+use crate::domain::offline_message::OfflineMessageCreate;
+use crate::service::offline_message_service::save_offline_message;
+use crate::utils::base64_utils;
+use crate::vo::message_vo::{BinaryMessageQuery, ClientPushVO, DeliveryStatus, MessagePushResultVo, MessageVO, NodeMessageVO, NodeTo, NodeToVo};
 use crate::web_socket::app_node::SessionUser;
+use crate::web_socket::node_route::publish_node_push;
+use crate::web_socket::{ack, rate_limit, session_registry};
+use crate::web_socket::topic::push_local;
+use crate::web_socket::node_registry::is_alive;
+use crate::web_socket::shutdown::purge_node;
+use log::{error, warn};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use uuid::Uuid;
 
 #[post("/api/push")]
-pub async fn push_handler(body: web::Json<PushRequest>, state: Data<AppState>) -> HttpResponse {
-    let manager = state.session_manager.clone();
-    let _ = &match body.client_id.as_deref() {
-        Some(x) => {
-            let addr = manager.get_session(&x);
-            if let Some(addr) = addr.await {
-                addr.do_send(ServerText(body.data.clone().unwrap()));
-            }
+pub async fn push_handler(body: web::Json<ClientPushVO>, state: Data<AppState>) -> Result<HttpResponse, AppError> {
+    let (Some(client_id), Some(data)) = (body.client_id.as_deref(), &body.data) else {
+        return Err(AppError::BadRequest("client_id and data are required".to_string()));
+    };
+    let delivered = match state.session_manager.get_session(client_id).await {
+        Some(addr) => {
+            metrics::mailbox_enqueued(client_id);
+            addr.do_send(ServerText(data.clone()));
+            true
         }
-        None => todo!(),
+        None => false,
     };
-    HttpResponse::Ok().body(format!("push via {}", state.app_name))
+    Ok(HttpResponse::Ok().json(json!(ResultVo::ok_with(json!({ "delivered": delivered })))))
 }
 
 #[post("/api/message/push")]
-pub async fn message_push_handler(body: web::Json<MessageVO>, state: Data<AppState>) -> HttpResponse {
-    let config = get_config().expect("TODO: panic message");
+pub async fn message_push_handler(
+    body: web::Json<MessageVO>,
+    request_id: Option<web::ReqData<RequestId>>,
+    state: Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let _timer = metrics::PUSH_DURATION.start_timer(&["message"]);
+    push_message(body.into_inner(), request_id.map(|id| id.into_inner().0), state).await
+}
+
+// 二进制消息推送：请求体为原始二进制数据（application/octet-stream），其余参数通过 query 传入
+#[post("/api/message/push/binary")]
+pub async fn binary_push_handler(
+    query: web::Query<BinaryMessageQuery>,
+    payload: Bytes,
+    request_id: Option<web::ReqData<RequestId>>,
+    state: Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let _timer = metrics::PUSH_DURATION.start_timer(&["binary"]);
+    let query = query.into_inner();
+    let body = MessageVO {
+        app_id: query.app_id,
+        app_token: query.app_token,
+        message: base64_utils::encode(&payload),
+        user_ids: query
+            .user_ids
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(str::to_string)
+            .collect(),
+        ack: false,
+        binary: true,
+    };
+    push_message(body, request_id.map(|id| id.into_inner().0), state).await
+}
+
+async fn push_message(body: MessageVO, request_id: Option<String>, state: Data<AppState>) -> Result<HttpResponse, AppError> {
+    // 二进制消息无法封装进确认信封
+    if body.binary && body.ack {
+        return Err(AppError::BadRequest("Ack is not supported for binary messages".to_string()));
+    }
+    let binary = match body.binary.then(|| base64_utils::decode(&body.message)) {
+        Some(Ok(data)) => Some(data),
+        Some(Err(_)) => return Err(AppError::BadRequest("Invalid base64 message".to_string())),
+        None => None,
+    };
+
+    let config = state.config.current();
+    check_push_rate(&state.redis, &config, &body.app_id).await?;
 
     let node_config = config.clone();
     // 获取
     let redis = state.redis.clone();
     let manager = state.session_manager.clone();
+
+    // 需要确认的消息封装为信封，所有用户共用同一个消息ID
+    let message_id = body.ack.then(|| Uuid::new_v4().to_string());
+    let message = match &message_id {
+        Some(id) => ack::envelope(id, &body.message),
+        None => body.message.clone(),
+    };
+
     let mut node_list :NodeMessageVO = NodeMessageVO::init(
         body.app_id.clone(),
         body.app_token.clone(),
-        body.message.clone(),
+        message.clone(),
     );
-    for user_id in &body.user_ids {
-        let redis_session_key = format!(
-            "web:socket:app_id:{}:user:id:{}",
-            body.app_id, user_id
-        );
+    node_list.message_id = message_id.clone();
+    node_list.binary = body.binary;
+    node_list.request_id = request_id;
 
-        let user_session =
-            redis.get_not_null(&redis_session_key)
-                .expect("TODO: panic message");
+    let mut status: HashMap<String, DeliveryStatus> = HashMap::new();
+    // 已记录未确认消息的用户，改为离线消息时需先删除
+    let mut pending_users: HashSet<String> = HashSet::new();
+    // 一次 pipeline 读取全部用户的会话
+    let session_users: Vec<Option<SessionUser>> = match session_registry::list_many(&redis, &body.app_id, &body.user_ids).await {
+        Ok(users) => users.into_iter().map(Some).collect(),
+        Err(e) => {
+            error!("Load sessions of {} users failed: {:?}", body.user_ids.len(), e);
+            body.user_ids.iter().map(|_| None).collect()
+        }
+    };
+    for (user_id, session_user) in body.user_ids.iter().zip(session_users) {
+        let Some(session_user) = session_user else {
+            status.insert(user_id.clone(), DeliveryStatus::Offline);
+            continue;
+        };
 
-        if user_session.is_empty() {
+        if session_user.nodes.is_empty() {
+            // 不在线，写入离线消息
+            let user_status = save_offline(&state, &config, &body.app_id, user_id, &message, message_id.as_deref(), body.binary).await;
+            status.insert(user_id.clone(), user_status);
             continue;
         }
 
-        let mut is_update = false;
-        let mut session_user: SessionUser = serde_json::from_str(&user_session).unwrap();
-        let mut nodes_to_remove = Vec::new();
-        for (index, user) in session_user.nodes.iter().enumerate() {
+        // 先记录未确认消息，连接断开或投递失败后可在重连时重发
+        if let Some(id) = &message_id {
+            match ack::store_pending(&redis, &body.app_id, user_id, id, &message, config.ack_pending_ttl).await {
+                Ok(_) => {
+                    pending_users.insert(user_id.clone());
+                }
+                Err(e) => error!("Store pending message for user {} failed: {:?}", user_id, e),
+            }
+        }
+
+        let mut delivered = false;
+        let mut queued = false;
+        for user in session_user.nodes.iter() {
             let url = format!("http://{}:{}/api/node/push", user.ip, user.port);
             if user.ip == config.app_ip && config.port == user.port { // 链接在这个链接上发送数据
-                let bool = send_message(&body.message, &user.session_id,&manager);
-                if !bool.await { // 节点不存在
-                    // 记录要删除的节点索引
-                    nodes_to_remove.push(index);
-                    is_update=true;
+                if send_local(&manager, &user.session_id, &message, binary.as_ref(), message_id.as_deref()).await {
+                    delivered = true;
+                } else { // 节点不存在
+                    remove_dead_session(&redis, &body.app_id, user_id, &user.session_id).await;
                 }
             }else{
+                queued = true;
                 let mut is_add = true;
                 // 不在这个链接上发送数据
                 for node in node_list.node_to.iter() {
@@ -76,12 +173,10 @@ pub async fn message_push_handler(body: web::Json<MessageVO>, state: Data<AppSta
                     }
                 }
                 if is_add {
-                    is_add = true;
                     let mut found = false;
                     for node_mut in node_list.node_to.iter_mut() {
                         if url == node_mut.base_url {
                             node_mut.user_ids.push(user_id.clone());
-                            is_add = false;
                             found = true;
                             break;
                         }
@@ -99,121 +194,269 @@ pub async fn message_push_handler(body: web::Json<MessageVO>, state: Data<AppSta
             }
         }
 
+        let user_status = if delivered {
+            DeliveryStatus::Delivered
+        } else if queued {
+            DeliveryStatus::Queued
+        } else {
+            // 记录的连接都已失效，改为离线消息（避免重连后重复投递）
+            fallback_offline(&state, &config, &body, user_id, &message, message_id.as_deref(), pending_users.contains(user_id)).await
+        };
+        status.insert(user_id.clone(), user_status);
+    }
 
-
-        // 逆序删除节点，避免索引变化问题
-        for &index in nodes_to_remove.iter().rev() {
-            session_user.nodes.remove(index);
-        }
-        if is_update {
-            let data = serde_json::to_string(&session_user).unwrap();
-            redis.set(&redis_session_key, &data).expect("TODO: panic message");
+    // 节点转发，转发失败且没有在本节点送达的用户改为离线消息
+    for user_id in forward_nodes(node_list, node_config, redis, state.http.clone()).in_current_span().await {
+        if status.get(&user_id) != Some(&DeliveryStatus::Queued) {
+            continue;
         }
+        let user_status =
+            fallback_offline(&state, &config, &body, &user_id, &message, message_id.as_deref(), pending_users.contains(&user_id)).await;
+        status.insert(user_id, user_status);
     }
 
+    Ok(HttpResponse::Ok().json(json!(ResultVo::ok_with(MessagePushResultVo {
+        message_id,
+        status,
+    }))))
+}
 
-    // 节点转发
-    spawn(async move {
-        forward_nodes(node_list, node_config, body).await;
-    });
 
-    HttpResponse::Ok().body(format!("push via {}", "aaa"))
+
+// 应用推送接口限流，超限返回 429
+pub(crate) async fn check_push_rate(redis: &RedisManager, config: &Config, app_id: &str) -> Result<(), AppError> {
+    match rate_limit::allow(redis, &rate_limit::app_push_rate_key(app_id), config.rate_app_push_per_sec).await {
+        Ok(true) => Ok(()),
+        Ok(false) => {
+            warn!("App {} exceeded push rate limit", app_id);
+            Err(AppError::TooManyRequests("Push rate limit exceeded".to_string()))
+        }
+        Err(e) => {
+            // Redis 不可用时放行
+            error!("Check push rate limit of app {} failed: {:?}", app_id, e);
+            Ok(())
+        }
+    }
 }
 
+// 在线投递失败，删除已记录的未确认消息（避免重连后重复投递）后写入离线消息
+async fn fallback_offline(
+    state: &AppState,
+    config: &Config,
+    body: &MessageVO,
+    user_id: &str,
+    message: &str,
+    message_id: Option<&str>,
+    pending_stored: bool,
+) -> DeliveryStatus {
+    if pending_stored
+        && let Some(id) = message_id
+        && let Err(e) = ack::remove_pending(&state.redis, &body.app_id, user_id, id).await
+    {
+        error!("Remove pending message for user {} failed: {:?}", user_id, e);
+    }
+    save_offline(state, config, &body.app_id, user_id, message, message_id, body.binary).await
+}
 
+// 写入离线消息，成功返回 Queued，失败返回 Offline
+async fn save_offline(
+    state: &AppState,
+    config: &Config,
+    app_id: &str,
+    user_id: &str,
+    message: &str,
+    message_id: Option<&str>,
+    binary: bool,
+) -> DeliveryStatus {
+    let offline = OfflineMessageCreate {
+        app_id: app_id.to_string(),
+        user_id: user_id.to_string(),
+        message_id: message_id.map(str::to_string),
+        message: message.to_string(),
+        binary,
+        ttl: config.offline_ttl,
+    };
+    match save_offline_message(&state.db, offline, config.offline_max_per_user).await {
+        Ok(_) => DeliveryStatus::Queued,
+        Err(e) => {
+            error!("Save offline message for user {} failed: {:?}", user_id, e);
+            DeliveryStatus::Offline
+        }
+    }
+}
 
 // 节点转发 的 消息
 #[post("/api/node/push")]
 pub async fn node_push_handler(body: web::Json<NodeTo>, state: Data<AppState>) -> HttpResponse {
+    let config = state.config.current();
 
-    let redis = state.redis.clone();
-    let manager = state.session_manager.clone();
-
-    let config = get_config().unwrap();
+    deliver_node_push(&body, &state.redis, &state.session_manager, &config).await;
 
-    for user_id in body.node.user_ids.iter()  {
-        let redis_session_key = format!(
-            "web:socket:app_id:{}:user:id:{}",
-            body.app_id, user_id
-        );
+    HttpResponse::Ok().json(json!(ResultVo::ok_with("ok")))
+}
 
-        let user_session =
-            redis.get_not_null(&redis_session_key)
-                .expect("TODO: panic message");
+// 投递其他节点转发过来的消息（HTTP 与 Redis pub/sub 两种路由共用）
+pub(crate) async fn deliver_node_push(
+    body: &NodeTo,
+    redis: &RedisManager,
+    manager: &SessionManager,
+    config: &Config,
+) {
+    // 主题消息：投递给本节点上的订阅者
+    if let Some(topic) = &body.topic {
+        if let Err(e) = push_local(redis, manager, config, &body.app_id, topic, &body.message).await {
+            error!("Topic {} local push failed: {:?}", topic, e);
+        }
+        return;
+    }
 
-        let mut session_user: SessionUser = serde_json::from_str(&user_session).unwrap();
-        let mut nodes_to_remove = Vec::new();
-        let mut is_update = false;
-        for (index, user) in session_user.nodes.iter().enumerate() {
-            if user.ip == config.app_ip && config.port == user.port { // 链接在这个链接上发送数据
-                let bool = send_message(&body.message, &user.session_id,&manager);
-                if !bool.await { // 节点不存在
-                    // 记录要删除的节点索引
-                    nodes_to_remove.push(index);
-                    is_update=true;
-                }
-            }
+    let binary = match body.binary.then(|| base64_utils::decode(&body.message)) {
+        Some(Ok(data)) => Some(data),
+        Some(Err(e)) => {
+            error!("Decode binary message from node failed: {:?}", e);
+            return;
         }
-        // 逆序删除节点，避免索引变化问题
-        for &index in nodes_to_remove.iter().rev() {
-            session_user.nodes.remove(index);
+        None => None,
+    };
+
+    let session_users = match session_registry::list_many(redis, &body.app_id, &body.node.user_ids).await {
+        Ok(users) => users,
+        Err(e) => {
+            error!("Load sessions of {} users failed: {:?}", body.node.user_ids.len(), e);
+            return;
         }
-        if is_update {
-            let data = serde_json::to_string(&session_user).unwrap();
-            redis.set(&redis_session_key, &data).expect("TODO: panic message");
+    };
+    for (user_id, session_user) in body.node.user_ids.iter().zip(session_users) {
+
+        for user in session_user.nodes.iter() {
+            if user.ip == config.app_ip && config.port == user.port // 链接在这个链接上发送数据
+                && !send_local(manager, &user.session_id, &body.message, binary.as_ref(), body.message_id.as_deref()).await // 节点不存在
+            {
+                remove_dead_session(redis, &body.app_id, user_id, &user.session_id).await;
+            }
         }
     }
-    HttpResponse::Ok().body(format!("push via {}", "aaa"))
 }
 
+// 发送到本节点的连接，二进制消息以二进制帧发送
+async fn send_local(
+    manager: &SessionManager,
+    session_id: &str,
+    message: &str,
+    binary: Option<&Bytes>,
+    message_id: Option<&str>,
+) -> bool {
+    match binary {
+        Some(data) => manager.send_binary(session_id, data.clone()).await,
+        None => manager.send_message(session_id, message, message_id).await,
+    }
+}
 
-async fn send_message(message: &String, session_id: &String, manager: &SessionManager) -> bool {
-    let addr = manager.get_session(session_id).await;
-    
-    if let Some(addr) = addr {
-        addr.do_send(ServerText(message.clone()));
-        true
-    } else {
-        false
+// 删除本节点上已不存在的会话记录
+async fn remove_dead_session(redis: &RedisManager, app_id: &str, user_id: &str, session_id: &str) {
+    if let Err(e) = session_registry::unregister(redis, app_id, user_id, session_id).await {
+        error!("Remove dead session {} failed: {:?}", session_id, e);
     }
 }
 
 
-// 节点转发
-async fn forward_nodes(
+// 节点转发，返回消息未能送到的用户（节点心跳过期、发布或请求失败）
+pub(crate) async fn forward_nodes(
     node_list: NodeMessageVO,
-    node_config: Config,
-    body: web::Json<MessageVO>
-) {
-
+    node_config: Arc<Config>,
+    redis: Data<RedisManager>,
+    http: HttpClient,
+) -> Vec<String> {
+    let mut failed: Vec<String> = vec![];
     for node in node_list.node_to.iter() {
-
-
-        let mut headers: Vec<(&str, &str)> = vec![];
-
-        if let Some(nodes) = &node_config.node_config {
-            for node_cfg in nodes {
-                if node_cfg.port == node.port && node_cfg.ip == node.ip {
-                    headers.push(("loc_to_token", &node_cfg.token));
-                }
+        // 心跳已过期的节点不再转发，清理它留下的会话记录，这些用户的消息由调用方改存离线
+        if let Ok(false) = is_alive(&redis, &node.ip, node.port).await {
+            warn!("Node {}:{} heartbeat expired, dropping its sessions", node.ip, node.port);
+            if let Err(e) = purge_node(&redis, &node.ip, node.port).await {
+                error!("Purge node {}:{} failed: {:?}", node.ip, node.port, e);
             }
+            failed.extend(node.user_ids.iter().cloned());
+            continue;
         }
-        headers.push(("Content-Type", "application/json"));
 
         let data: NodeTo = NodeTo{
             node: node.clone(),
-            app_id: body.app_id.clone(),
-            app_token: body.app_token.clone(),
-            message: body.message.clone(),
+            app_id: node_list.app_id.clone(),
+            app_token: node_list.app_token.clone(),
+            message: node_list.message.clone(),
+            topic: node_list.topic.clone(),
+            message_id: node_list.message_id.clone(),
+            binary: node_list.binary,
+            request_id: node_list.request_id.clone(),
         };
-        let data = &serde_json::to_string(&data).unwrap();
-        match http_post(&node.base_url,data,&headers).await {
-            Ok(_) => {
 
-            },
-            Err(_) => {
+        // Redis pub/sub 路由：发布到目标节点的频道
+        if node_config.node_route == NodeRoute::Pubsub {
+            if let Err(e) = publish_node_push(&redis, &data).await {
+                error!("Publish to node {}:{} failed: {:?}", node.ip, node.port, e);
+                metrics::FORWARD_FAILURES_TOTAL.inc(&[&format!("{}:{}", node.ip, node.port)]);
+                failed.extend(node.user_ids.iter().cloned());
+            }
+            continue;
+        }
+
+        let mut headers: Vec<(&str, &str)> = vec![];
 
+        // 优先使用静态配置中的节点 token，注册中心发现的节点使用集群共享的 node_token
+        let token = node_config
+            .node_config
+            .iter()
+            .flatten()
+            .find(|node_cfg| node_cfg.port == node.port && node_cfg.ip == node.ip)
+            .map(|node_cfg| node_cfg.token.as_str())
+            .unwrap_or(&node_config.node_token);
+        headers.push(("loc_to_token", token));
+        headers.push(("Content-Type", "application/json"));
+        if let Some(request_id) = &node_list.request_id {
+            headers.push((REQUEST_ID_HEADER, request_id));
+        }
+
+        let data = match serde_json::to_string(&data) {
+            Ok(data) => data,
+            Err(e) => {
+                error!("Serialize node push failed: {:?}", e);
+                failed.extend(node.user_ids.iter().cloned());
+                continue;
             }
+        };
+        // 只在连接失败时重试，已发出的请求不重试，避免重复投递
+        if let Err(e) = http.post_with_retry(&node.base_url,&data,&headers).await {
+            error!("Forward to node {}:{} failed: {}", node.ip, node.port, e);
+            metrics::FORWARD_FAILURES_TOTAL.inc(&[&format!("{}:{}", node.ip, node.port)]);
+            failed.extend(node.user_ids.iter().cloned());
         }
     }
+    failed.sort();
+    failed.dedup();
+    failed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    #[ignore = "requires Redis (WS_TEST_REDIS_URL)"]
+    async fn forward_returns_users_of_expired_nodes() {
+        let url = std::env::var("WS_TEST_REDIS_URL").expect("WS_TEST_REDIS_URL is not set");
+        let redis = Data::new(RedisManager::new(&url).unwrap());
+        let config: Config = serde_yaml::from_str(include_str!("../../config.yaml")).unwrap();
+        let http = HttpClient::new(&config).unwrap();
+
+        // 没有心跳记录的节点视为已下线，消息不转发，用户交给调用方改存离线
+        let mut node_list = NodeMessageVO::init("app".to_string(), "token".to_string(), "hello".to_string());
+        node_list.node_to.push(NodeToVo::new(
+            "http://10.255.255.1:1/api/node/push".to_string(),
+            vec!["u2".to_string(), "u1".to_string()],
+            "10.255.255.1".to_string(),
+            1,
+        ));
+        let failed = forward_nodes(node_list, Arc::new(config), redis, http).await;
+        assert_eq!(failed, ["u1", "u2"]);
+    }
 }
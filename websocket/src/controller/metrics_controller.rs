@@ -0,0 +1,10 @@
+use actix_web::{get, HttpResponse};
+use crate::common::metrics;
+
+// Prometheus 抓取接口，只在 metrics_port 上提供，不经过 token 认证
+#[get("/metrics")]
+pub async fn get_metrics() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics::render())
+}
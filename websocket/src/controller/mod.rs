@@ -1,5 +1,12 @@
 pub mod message_controller;
 pub mod user_controller;
+pub mod topic_controller;
+pub mod presence_controller;
+pub mod node_controller;
+pub mod application_use_controller;
+pub mod session_controller;
+pub mod metrics_controller;
+pub mod health_controller;
 
 use actix_web::web;
 
@@ -13,10 +20,41 @@ pub fn config_services(cfg: &mut web::ServiceConfig) {
         .service(user_controller::delete_user)
         .service(user_controller::login)
 
+        // 应用管理（/api/app/page 需在 /api/app/{app_id} 之前注册）
+        .service(application_use_controller::get_page)
+        .service(application_use_controller::get_app)
+        .service(application_use_controller::create_app)
+        .service(application_use_controller::update_app)
+        .service(application_use_controller::rotate_token)
+        .service(application_use_controller::update_status)
+        .service(application_use_controller::delete_app)
+
         // 消息转发
         .service(message_controller::node_push_handler)
 
         // 消息控制器
         .service(message_controller::message_push_handler)
-        .service(message_controller::push_handler);
+        .service(message_controller::binary_push_handler)
+        .service(message_controller::push_handler)
+
+        // 主题推送
+        .service(topic_controller::topic_push_handler)
+
+        // 在线状态
+        .service(presence_controller::get_presence)
+        .service(presence_controller::set_watchers)
+
+        // 会话管理
+        .service(session_controller::get_sessions)
+        .service(session_controller::kick_session)
+        .service(session_controller::kick_user_sessions)
+
+        // 节点注册中心
+        .service(node_controller::get_nodes)
+        .service(health_controller::start_drain)
+        .service(health_controller::stop_drain)
+
+        // 健康检查
+        .service(health_controller::healthz)
+        .service(health_controller::readyz);
 }
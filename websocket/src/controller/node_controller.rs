@@ -0,0 +1,13 @@
+use actix_web::{get, web::Data, HttpResponse};
+use serde_json::json;
+use crate::common::dto::ResultVo;
+use crate::common::error::AppError;
+use crate::web_socket::node_registry::list_nodes;
+use crate::web_socket::web_socket_server::AppState;
+
+// 注册中心中存活的节点
+#[get("/api/nodes")]
+pub async fn get_nodes(state: Data<AppState>) -> Result<HttpResponse, AppError> {
+    let nodes = list_nodes(&state.redis).await?;
+    Ok(HttpResponse::Ok().json(json!(ResultVo::ok_with(nodes))))
+}
@@ -0,0 +1,73 @@
+use actix_web::{get, put, web::{self, Data}, HttpResponse};
+use serde_json::json;
+use crate::common::dto::ResultVo;
+use crate::common::error::AppError;
+use crate::vo::presence_vo::{PresenceNodeVo, PresenceQuery, PresenceVo, PresenceWatchersVo};
+use crate::web_socket::app_node::SessionUser;
+use crate::web_socket::session_registry;
+use crate::web_socket::presence::{self, last_seen_key};
+use crate::web_socket::web_socket_server::AppState;
+
+// 单次最多查询的用户数
+const MAX_PRESENCE_USERS: usize = 500;
+
+#[get("/api/presence")]
+pub async fn get_presence(query: web::Query<PresenceQuery>, state: Data<AppState>) -> Result<HttpResponse, AppError> {
+    let user_ids: Vec<String> = query
+        .user_ids
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+        .collect();
+    if user_ids.is_empty() || user_ids.len() > MAX_PRESENCE_USERS {
+        return Err(AppError::BadRequest(format!("user_ids must contain 1 to {} ids", MAX_PRESENCE_USERS)));
+    }
+
+    // 会话登记一次 pipeline，最后在线时间一次 MGET
+    let sessions = session_registry::list_many(&state.redis, &query.app_id, &user_ids).await?;
+    let last_seen_keys: Vec<String> = user_ids.iter().map(|user_id| last_seen_key(&query.app_id, user_id)).collect();
+    let last_seen = state.redis.async_mget(&last_seen_keys).await?;
+    let list: Vec<PresenceVo> = user_ids
+        .into_iter()
+        .zip(sessions)
+        .zip(last_seen)
+        .map(|((user_id, session_user), last_seen)| {
+            user_presence(user_id, session_user, last_seen.and_then(|t| t.parse::<u64>().ok()))
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(json!(ResultVo::ok_with(list))))
+}
+
+// 替换允许订阅该用户上下线事件（presence:{user_id} 主题）的用户列表，空列表表示只允许本人订阅
+#[put("/api/presence/watchers")]
+pub async fn set_watchers(body: web::Json<PresenceWatchersVo>, state: Data<AppState>) -> Result<HttpResponse, AppError> {
+    if body.watchers.len() > MAX_PRESENCE_USERS {
+        return Err(AppError::BadRequest(format!("watchers must contain at most {} ids", MAX_PRESENCE_USERS)));
+    }
+    presence::set_watchers(&state.redis, &body.app_id, &body.user_id, &body.watchers).await?;
+    Ok(HttpResponse::Ok().json(json!(ResultVo::ok_with(body.watchers.len()))))
+}
+
+fn user_presence(user_id: String, session_user: SessionUser, last_seen: Option<u64>) -> PresenceVo {
+    // 按节点汇总会话数
+    let mut nodes: Vec<PresenceNodeVo> = vec![];
+    for app_node in session_user.nodes.iter() {
+        match nodes.iter_mut().find(|n| n.ip == app_node.ip && n.port == app_node.port) {
+            Some(node) => node.sessions += 1,
+            None => nodes.push(PresenceNodeVo {
+                ip: app_node.ip.clone(),
+                port: app_node.port,
+                sessions: 1,
+            }),
+        }
+    }
+
+    PresenceVo {
+        user_id,
+        online: !session_user.nodes.is_empty(),
+        session_count: session_user.nodes.len(),
+        nodes,
+        last_seen,
+    }
+}
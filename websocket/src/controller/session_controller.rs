@@ -0,0 +1,129 @@
+use actix_web::{delete, get, web::{self, Data}, HttpResponse};
+use actix_web_actors::ws::CloseCode;
+use log::{error, info, warn};
+use serde_json::json;
+use crate::common::dto::ResultVo;
+use crate::common::error::AppError;
+use crate::vo::session_vo::{KickQuery, SessionPageVo, SessionQuery, SessionVo};
+use crate::web_socket::app_node::AppNode;
+use crate::web_socket::session_control;
+use crate::web_socket::session_registry::{self, LiveSession};
+use crate::web_socket::web_socket_server::AppState;
+
+// 单页最多扫描的会话 key 数
+const MAX_PAGE_SIZE: usize = 500;
+
+#[get("/api/sessions")]
+pub async fn get_sessions(query: web::Query<SessionQuery>, state: Data<AppState>) -> Result<HttpResponse, AppError> {
+    if query.page_size == 0 || query.page_size > MAX_PAGE_SIZE {
+        return Err(AppError::BadRequest(format!("page_size must be 1 to {}", MAX_PAGE_SIZE)));
+    }
+
+    let (next_cursor, sessions) = session_registry::list_sessions_page(
+        &state.redis,
+        query.app_id.as_deref(),
+        query.user_id.as_deref(),
+        query.cursor,
+        query.page_size,
+    )
+    .await?;
+    let list = sessions.into_iter().map(session_vo).collect();
+    Ok(HttpResponse::Ok().json(json!(ResultVo::ok_with(SessionPageVo { next_cursor, list }))))
+}
+
+#[delete("/api/sessions/{session_id}")]
+pub async fn kick_session(
+    session_id: web::Path<String>,
+    query: web::Query<KickQuery>,
+    state: Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let (code, reason) = close_reason(&query)?;
+    // 本节点上的会话直接断开
+    if state.session_manager.disconnect(&session_id, CloseCode::from(code), reason).await {
+        info!("Kicked local session {}", session_id);
+        return Ok(HttpResponse::Ok().json(json!(ResultVo::ok_with(1))));
+    }
+
+    // 其他节点上的会话：未指定 app_id 与 user_id 时广播给所有节点，由会话所在节点断开
+    let (Some(app_id), Some(user_id)) = (&query.app_id, &query.user_id) else {
+        let config = state.config.current();
+        let receivers = session_control::broadcast_kick(&state.redis, &config, &session_id, code, reason).await?;
+        if receivers == 0 {
+            return Err(AppError::NotFound("Session not found".to_string()));
+        }
+        info!("Kick of session {} broadcast to {} nodes", session_id, receivers);
+        return Ok(HttpResponse::Ok().json(json!(ResultVo::ok_with(1))));
+    };
+    let sessions = session_registry::list(&state.redis, app_id, user_id).await?;
+    let Some(node) = sessions.nodes.into_iter().find(|node| node.session_id == *session_id) else {
+        return Err(AppError::NotFound("Session not found".to_string()));
+    };
+    let kicked = kick_sessions(&state, user_id, vec![node], &query).await?;
+    if kicked == 0 {
+        return Err(AppError::NotFound("Session not found".to_string()));
+    }
+    Ok(HttpResponse::Ok().json(json!(ResultVo::ok_with(kicked))))
+}
+
+#[delete("/api/users/{app_id}/{user_id}/sessions")]
+pub async fn kick_user_sessions(
+    path: web::Path<(String, String)>,
+    query: web::Query<KickQuery>,
+    state: Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let (app_id, user_id) = path.into_inner();
+    let sessions = session_registry::list(&state.redis, &app_id, &user_id).await?;
+    let kicked = kick_sessions(&state, &user_id, sessions.nodes, &query).await?;
+    Ok(HttpResponse::Ok().json(json!(ResultVo::ok_with(kicked))))
+}
+
+// 关闭码与原因：只允许正常关闭（1000）及应用自定义关闭码（3000-4999），原因超出关闭帧长度时截断
+fn close_reason(query: &KickQuery) -> Result<(u16, &str), AppError> {
+    let code = query.code.unwrap_or(session_control::CLOSE_KICKED);
+    if code != 1000 && !(3000..=4999).contains(&code) {
+        return Err(AppError::BadRequest("code must be 1000 or 3000 to 4999".to_string()));
+    }
+    let reason = query.reason.as_deref().unwrap_or("kicked by admin");
+    Ok((code, session_control::truncate_reason(reason)))
+}
+
+/// 断开会话，其他节点上的会话通过控制频道转发
+/// 返回实际断开的数量：所在节点没有收到指令（已下线）的会话不计入
+async fn kick_sessions(state: &AppState, user_id: &str, nodes: Vec<AppNode>, query: &KickQuery) -> Result<usize, AppError> {
+    let (code, reason) = close_reason(query)?;
+    let config = state.config.current();
+    let mut kicked = 0;
+    for node in nodes {
+        info!("Kicking session {} of user {} on {}:{}", node.session_id, user_id, node.ip, node.port);
+        let result = session_control::kick(
+            &state.redis,
+            &state.session_manager,
+            &config,
+            &node,
+            code,
+            reason,
+        )
+        .await;
+        match result {
+            Ok(true) => kicked += 1,
+            Ok(false) => warn!("Session {} not found on {}:{}", node.session_id, node.ip, node.port),
+            Err(e) => error!("Kick session {} failed: {:?}", node.session_id, e),
+        }
+    }
+    Ok(kicked)
+}
+
+fn session_vo(session: LiveSession) -> SessionVo {
+    SessionVo {
+        session_id: session.node.session_id,
+        app_id: session.app_id,
+        user_id: session.user_id,
+        node_ip: session.node.ip,
+        node_port: session.node.port,
+        remote_ip: session.node.remote_ip,
+        device: session.node.device,
+        connected_at: session.node.connected_at,
+        messages_in: session.node.messages_in,
+        messages_out: session.node.messages_out,
+    }
+}
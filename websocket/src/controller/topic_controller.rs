@@ -0,0 +1,49 @@
+use actix::spawn;
+use tracing::Instrument;
+use actix_web::{HttpResponse, post, web::{self, Data}};
+use serde_json::json;
+use crate::common::dto::ResultVo;
+use crate::common::error::AppError;
+use crate::common::metrics;
+use crate::config::middleware::RequestId;
+use crate::controller::message_controller::{check_push_rate, forward_nodes};
+use crate::vo::message_vo::{NodeMessageVO, TopicMessageVO};
+use crate::web_socket::topic::{is_valid_topic, route_topic};
+use crate::web_socket::web_socket_server::AppState;
+
+// 主题推送：本节点订阅者直接投递，其他节点上的订阅者通过节点转发
+#[post("/api/topic/push")]
+pub async fn topic_push_handler(
+    body: web::Json<TopicMessageVO>,
+    request_id: Option<web::ReqData<RequestId>>,
+    state: Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let _timer = metrics::PUSH_DURATION.start_timer(&["topic"]);
+    if !is_valid_topic(&body.topic) {
+        return Err(AppError::BadRequest("Invalid topic".to_string()));
+    }
+
+    let config = state.config.current();
+    check_push_rate(&state.redis, &config, &body.app_id).await?;
+    let body = body.into_inner();
+    let mut node_list = NodeMessageVO::init(body.app_id, body.app_token, body.message);
+    node_list.topic = Some(body.topic.clone());
+    node_list.request_id = request_id.map(|id| id.into_inner().0);
+
+    let delivered = route_topic(&state.redis, &state.session_manager, &config, &mut node_list, &body.topic).await?;
+    let forwarded = node_list.node_to.len();
+    if forwarded > 0 {
+        let redis = state.redis.clone();
+        let http = state.http.clone();
+        spawn(
+            async move {
+                forward_nodes(node_list, config, redis, http).await;
+            }
+            .in_current_span(),
+        );
+    }
+    Ok(HttpResponse::Ok().json(json!(ResultVo::ok_with(json!({
+        "delivered": delivered,
+        "forwarded_nodes": forwarded,
+    })))))
+}
@@ -1,101 +1,47 @@
-use actix_web::{get, post,put,delete, web, HttpRequest, HttpResponse};
+use actix_web::{get, post,put,delete, web, HttpResponse};
 use actix_web::web::Data;
 use serde_json::json;
 use crate::common::dto::ResultVo;
+use crate::common::error::AppError;
+use crate::config::redis_manager::RedisManager;
 use crate::service::user_service;
 use crate::domain::user::{UserCreate, UserLogin, UserQuery, UserUpdate};
 use crate::db::obj::DbState;
+use crate::web_socket::web_socket_server::AppState;
 
 #[get("/api/user/page")]
-pub async fn get_page(req: HttpRequest, query: web::Query<UserQuery>) -> HttpResponse {
-    // 使用从应用状态获取的数据库连接池
-    let db_state = req.app_data::<Data<DbState>>().unwrap();
-    let pool = &db_state.db;
-
-    match user_service::get_page(pool, query.into_inner()).await {
-        Ok(users) => {
-            let response = json!(ResultVo::ok_with(users));
-            HttpResponse::Ok().json(response)
-        }
-        Err(e) => {
-            HttpResponse::InternalServerError().json(json!(
-                ResultVo::<()>::error(1, e.to_string())
-            ))
-        }
-    }
+pub async fn get_page(db_state: Data<DbState>, query: web::Query<UserQuery>) -> Result<HttpResponse, AppError> {
+    let users = user_service::get_page(&db_state.db, query.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(json!(ResultVo::ok_with(users))))
 }
 
 
 #[post("/api/user")]
-pub async fn create_user(user_create: web::Json<UserCreate>,req: HttpRequest) -> HttpResponse {
-
-    let db_state = req.app_data::<Data<DbState>>().unwrap();
-    let pool = &db_state.db;
-
-    match user_service::create_user(pool, user_create.into_inner()).await {
-        Ok(user) => {
-            let response = json!(ResultVo::ok_with(user));
-            HttpResponse::Ok().json(response)
-        }
-        Err(e) => {
-            HttpResponse::InternalServerError().json(json!(
-                ResultVo::<()>::error(1, e.to_string())
-            ))
-        }
-    }
+pub async fn create_user(user_create: web::Json<UserCreate>, db_state: Data<DbState>) -> Result<HttpResponse, AppError> {
+    let user = user_service::create_user(&db_state.db, user_create.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(json!(ResultVo::ok_with(user))))
 }
 
 #[put("/api/user")]
-pub async fn update_user(user_update: web::Json<UserUpdate>,req: HttpRequest) -> HttpResponse {
-    let db_state = req.app_data::<Data<DbState>>().unwrap();
-    let pool = &db_state.db;
-    match user_service::update_user(pool, user_update.into_inner()).await {
-        Ok(number) => {
-            let response = json!(ResultVo::ok_with(number));
-            HttpResponse::Ok().json(response)
-        }
-        Err(e) => {
-            HttpResponse::InternalServerError().json(json!(
-                ResultVo::<()>::error(1, e.to_string())
-            ))
-        }
-    }
+pub async fn update_user(user_update: web::Json<UserUpdate>, db_state: Data<DbState>) -> Result<HttpResponse, AppError> {
+    let number = user_service::update_user(&db_state.db, user_update.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(json!(ResultVo::ok_with(number))))
 }
 
 #[delete("/api/user/{id}")]
-pub async fn delete_user(req: HttpRequest) -> HttpResponse {
-    let user_id: i32 = req.match_info().get("id").unwrap().parse().unwrap();
-    let db_state = req.app_data::<Data<DbState>>().unwrap();
-    let pool = &db_state.db;
-
-    match user_service::delete_user(pool, user_id).await {
-        Ok(number) => {
-            let response = json!(ResultVo::ok_with(number));
-            HttpResponse::Ok().json(response)
-        }
-        Err(e) => {
-            HttpResponse::InternalServerError().json(json!(
-                ResultVo::<()>::error(1, e.to_string())
-            ))
-        }
-    }
+pub async fn delete_user(user_id: web::Path<i32>, db_state: Data<DbState>) -> Result<HttpResponse, AppError> {
+    let number = user_service::delete_user(&db_state.db, user_id.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(json!(ResultVo::ok_with(number))))
 }
 
 #[post("/api/login")]
-pub async fn login(user_create: web::Json<UserLogin>,req: HttpRequest) -> HttpResponse {
-    let db_state = req.app_data::<Data<DbState>>().unwrap();
-    let pool = &db_state.db;
-    let redis_manager = req.app_data::<Data<crate::config::redis_manager::RedisManager>>().unwrap();
-
-    match user_service::login(pool, user_create.into_inner(), redis_manager).await {
-        Ok(token) => {
-            let response = json!(ResultVo::ok_with(token));
-            HttpResponse::Ok().json(response)
-        },
-        Err(e) => {
-            HttpResponse::InternalServerError().json(json!(
-                ResultVo::<()>::error(1, e.to_string())
-            ))
-        }
-    }
-}
\ No newline at end of file
+pub async fn login(
+    user_create: web::Json<UserLogin>,
+    db_state: Data<DbState>,
+    redis_manager: Data<RedisManager>,
+    state: Data<AppState>,
+) -> Result<HttpResponse, AppError> {
+    let token_ex = state.config.current().token_ex;
+    let token = user_service::login(&db_state.db, user_create.into_inner(), &redis_manager, token_ex).await?;
+    Ok(HttpResponse::Ok().json(json!(ResultVo::ok_with(token))))
+}
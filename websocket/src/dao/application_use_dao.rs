@@ -1,10 +1,17 @@
-use crate::domain::application_use::ApplicationUse;
-use sqlx::PgPool;
+use crate::common::dto::PageVo;
+use crate::common::error::AppError;
+use crate::domain::application_use::{
+    ApplicationUse, ApplicationUsePageListVo, ApplicationUseQuery, ApplicationUseUpdate,
+};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+
+// 每页最大条数
+const MAX_PAGE_SIZE: i64 = 100;
 
 pub async fn find_app_id(pool: &PgPool, app_name: &str) -> Result<ApplicationUse, sqlx::Error> {
     let app_use = sqlx::query_as(
         r#"
-        select id,app_id,token,app_auth_url,app_callback_message from application_use where app_id = $1
+        select id,app_id,token,app_auth_url,app_callback_message,max_sessions_per_user,session_policy,auth_mode,enabled from application_use where app_id = $1
         "#,
     )
     .bind(app_name)
@@ -12,3 +19,93 @@ pub async fn find_app_id(pool: &PgPool, app_name: &str) -> Result<ApplicationUse
     .await?;
     Ok(app_use)
 }
+
+pub async fn find_page(
+    pool: &PgPool,
+    query: ApplicationUseQuery,
+) -> Result<PageVo<ApplicationUsePageListVo>, AppError> {
+    if query.page < 1 || !(1..=MAX_PAGE_SIZE).contains(&query.page_size) {
+        return Err(AppError::BadRequest(format!("page 必须大于 0，page_size 必须在 1 到 {} 之间", MAX_PAGE_SIZE)));
+    }
+    let (total,): (i64,) = sqlx::query_as("select count(*) from application_use")
+        .fetch_one(pool)
+        .await?;
+
+    let mut qb: QueryBuilder<Postgres> = QueryBuilder::new(
+        "select id,app_id,app_auth_url,app_callback_message,max_sessions_per_user,session_policy,auth_mode,enabled from application_use order by id",
+    );
+    qb.push(" limit ");
+    qb.push_bind(query.page_size);
+    qb.push(" offset ");
+    qb.push_bind((query.page - 1) * query.page_size);
+    let apps = qb
+        .build_query_as::<ApplicationUsePageListVo>()
+        .fetch_all(pool)
+        .await?;
+
+    Ok(PageVo::new(total, apps))
+}
+
+pub async fn create_app(pool: &PgPool, app: &ApplicationUse) -> Result<i64, sqlx::Error> {
+    let (id,): (i64,) = sqlx::query_as(
+        r#"
+        insert into application_use (app_id, token, app_auth_url, app_callback_message, max_sessions_per_user, session_policy, auth_mode, enabled)
+        values ($1, $2, $3, $4, $5, $6, $7, $8)
+        returning id
+        "#,
+    )
+    .bind(&app.app_id)
+    .bind(&app.token)
+    .bind(&app.app_auth_url)
+    .bind(&app.app_callback_message)
+    .bind(app.max_sessions_per_user)
+    .bind(&app.session_policy)
+    .bind(&app.auth_mode)
+    .bind(app.enabled)
+    .fetch_one(pool)
+    .await?;
+    Ok(id)
+}
+
+pub async fn update_app(pool: &PgPool, app: &ApplicationUseUpdate) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        update application_use set app_auth_url = $1, app_callback_message = $2, max_sessions_per_user = $3, session_policy = $4, auth_mode = $5 where app_id = $6
+        "#,
+    )
+    .bind(&app.app_auth_url)
+    .bind(&app.app_callback_message)
+    .bind(app.max_sessions_per_user)
+    .bind(&app.session_policy)
+    .bind(&app.auth_mode)
+    .bind(&app.app_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+pub async fn update_token(pool: &PgPool, app_id: &str, token: &str) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("update application_use set token = $1 where app_id = $2")
+        .bind(token)
+        .bind(app_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+pub async fn update_enabled(pool: &PgPool, app_id: &str, enabled: bool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("update application_use set enabled = $1 where app_id = $2")
+        .bind(enabled)
+        .bind(app_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+pub async fn delete_app(pool: &PgPool, app_id: &str) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("delete from application_use where app_id = $1")
+        .bind(app_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,25 @@
+use crate::domain::callback_dead_letter::CallbackDeadLetterCreate;
+use sqlx::PgPool;
+
+pub async fn create_dead_letter(
+    pool: &PgPool,
+    dead_letter: CallbackDeadLetterCreate,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        insert into callback_dead_letter (app_id, user_id, session_id, callback_url, message, attempts, last_error)
+        values ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+    )
+    .bind(dead_letter.app_id)
+    .bind(dead_letter.user_id)
+    .bind(dead_letter.session_id)
+    .bind(dead_letter.callback_url)
+    .bind(dead_letter.message)
+    .bind(dead_letter.attempts)
+    .bind(dead_letter.last_error)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
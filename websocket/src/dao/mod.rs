@@ -1,2 +1,4 @@
 pub mod user_dao;
-pub mod application_use_dao;
\ No newline at end of file
+pub mod application_use_dao;
+pub mod callback_dead_letter_dao;
+pub mod offline_message_dao;
\ No newline at end of file
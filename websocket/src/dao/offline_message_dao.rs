@@ -0,0 +1,87 @@
+use crate::domain::offline_message::{OfflineMessage, OfflineMessageCreate};
+use sqlx::{PgConnection, PgPool};
+
+pub async fn create_offline_message(
+    pool: &PgPool,
+    message: OfflineMessageCreate,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        insert into offline_message (app_id, user_id, message_id, message, binary, expire_time)
+        values ($1, $2, $3, $4, $5, now() + $6::float8 * interval '1 second')
+        "#,
+    )
+    .bind(message.app_id)
+    .bind(message.user_id)
+    .bind(message.message_id)
+    .bind(message.message)
+    .bind(message.binary)
+    .bind(message.ttl)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+// 删除过期消息及超出条数上限的最早消息
+pub async fn trim_offline_messages(
+    pool: &PgPool,
+    app_id: &str,
+    user_id: &str,
+    max_count: i64,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        delete from offline_message
+        where app_id = $1 and user_id = $2
+          and (expire_time <= now() or id not in (
+              select id from offline_message
+              where app_id = $1 and user_id = $2
+              order by id desc
+              limit $3
+          ))
+        "#,
+    )
+    .bind(app_id)
+    .bind(user_id)
+    .bind(max_count)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+// 在事务内锁定用户未过期的离线消息，按写入顺序排列；其他事务已锁定的行跳过，避免多个会话重复投递
+pub async fn claim_offline_messages(
+    conn: &mut PgConnection,
+    app_id: &str,
+    user_id: &str,
+) -> Result<Vec<OfflineMessage>, sqlx::Error> {
+    let messages = sqlx::query_as(
+        r#"
+        select id, message_id, message, binary from offline_message
+        where app_id = $1 and user_id = $2 and expire_time > now()
+        order by id
+        for update skip locked
+        "#,
+    )
+    .bind(app_id)
+    .bind(user_id)
+    .fetch_all(conn)
+    .await?;
+
+    Ok(messages)
+}
+
+// 删除已投递的离线消息
+pub async fn delete_offline_messages(conn: &mut PgConnection, ids: &[i64]) -> Result<u64, sqlx::Error> {
+    if ids.is_empty() {
+        return Ok(0);
+    }
+    let result = sqlx::query("delete from offline_message where id = any($1)")
+        .bind(ids)
+        .execute(conn)
+        .await?;
+
+    Ok(result.rows_affected())
+}
@@ -1,11 +1,157 @@
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
-#[derive(Debug, Serialize, FromRow, Deserialize)]
+#[derive(Debug, Clone, Serialize, FromRow, Deserialize)]
 pub struct ApplicationUse{
     pub id: i64,
     pub app_id: String,
     pub token: String,
     pub app_auth_url: String,
-    pub app_callback_message: String
-}
\ No newline at end of file
+    pub app_callback_message: String,
+    // 每个用户最大同时在线会话数，0 不限制
+    pub max_sessions_per_user: i32,
+    // 超出会话数时的策略
+    pub session_policy: String,
+    // 连接 token 的校验方式
+    pub auth_mode: String,
+    // 停用后拒绝新连接
+    pub enabled: bool,
+}
+
+/**
+ * 应用分页列表，不含密钥
+ */
+#[derive(Debug, Serialize, FromRow, Deserialize)]
+pub struct ApplicationUsePageListVo {
+    pub id: i64,
+    pub app_id: String,
+    pub app_auth_url: String,
+    pub app_callback_message: String,
+    pub max_sessions_per_user: i32,
+    pub session_policy: String,
+    pub auth_mode: String,
+    pub enabled: bool,
+    // 当前在线连接数（全部节点）
+    #[sqlx(skip)]
+    pub connections: usize,
+}
+
+/**
+ * 应用查询参数
+ */
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApplicationUseQuery {
+    pub page: i64,
+    pub page_size: i64,
+}
+
+/**
+ * 应用添加，app_id 与密钥由服务端生成
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApplicationUseCreate {
+    pub app_auth_url: String,
+    pub app_callback_message: String,
+    #[serde(default)]
+    pub max_sessions_per_user: i32,
+    #[serde(default = "default_session_policy")]
+    pub session_policy: String,
+    #[serde(default = "default_auth_mode")]
+    pub auth_mode: String,
+}
+
+/**
+ * 应用修改
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApplicationUseUpdate {
+    pub app_id: String,
+    pub app_auth_url: String,
+    pub app_callback_message: String,
+    pub max_sessions_per_user: i32,
+    pub session_policy: String,
+    pub auth_mode: String,
+}
+
+/**
+ * 应用启用 / 停用
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApplicationUseStatus {
+    pub enabled: bool,
+}
+
+fn default_session_policy() -> String { SessionPolicy::EvictOldest.as_str().to_string() }
+fn default_auth_mode() -> String { AuthMode::Http.as_str().to_string() }
+
+/**
+ * 超出会话数时的策略
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionPolicy {
+    // 拒绝新连接
+    Reject,
+    // 踢掉最早的会话
+    EvictOldest,
+    // 每种设备类型只保留一个会话，同设备的旧会话被踢掉
+    PerDevice,
+}
+
+impl SessionPolicy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "reject" => Some(SessionPolicy::Reject),
+            "evict_oldest" => Some(SessionPolicy::EvictOldest),
+            "per_device" => Some(SessionPolicy::PerDevice),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionPolicy::Reject => "reject",
+            SessionPolicy::EvictOldest => "evict_oldest",
+            SessionPolicy::PerDevice => "per_device",
+        }
+    }
+}
+
+/**
+ * 连接 token 的校验方式
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMode {
+    // 调用应用的 app_auth_url 校验
+    Http,
+    // 应用用 token 作为密钥签发 HS256 JWT，网关本地校验
+    Jwt,
+}
+
+impl AuthMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "http" => Some(AuthMode::Http),
+            "jwt" => Some(AuthMode::Jwt),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuthMode::Http => "http",
+            AuthMode::Jwt => "jwt",
+        }
+    }
+}
+
+impl ApplicationUse {
+    /// 未知的校验方式按 http 处理
+    pub fn auth_mode(&self) -> AuthMode {
+        AuthMode::parse(&self.auth_mode).unwrap_or(AuthMode::Http)
+    }
+
+    /// 未知的策略按 evict_oldest 处理
+    pub fn session_policy(&self) -> SessionPolicy {
+        SessionPolicy::parse(&self.session_policy).unwrap_or(SessionPolicy::EvictOldest)
+    }
+}
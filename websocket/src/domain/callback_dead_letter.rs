@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/**
+ * 回调投递失败的死信记录
+ */
+#[derive(Debug, Serialize, FromRow, Deserialize)]
+pub struct CallbackDeadLetterCreate {
+    pub app_id: String,
+    pub user_id: Option<String>,
+    pub session_id: String,
+    pub callback_url: String,
+    pub message: String,
+    pub attempts: i32,
+    pub last_error: String,
+}
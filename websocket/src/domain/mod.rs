@@ -1,2 +1,4 @@
 pub mod user;
-pub mod application_use;
\ No newline at end of file
+pub mod application_use;
+pub mod callback_dead_letter;
+pub mod offline_message;
\ No newline at end of file
@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/**
+ * 离线消息
+ */
+#[derive(Debug, Serialize, FromRow, Deserialize)]
+pub struct OfflineMessage {
+    pub id: i64,
+    pub message_id: Option<String>,
+    pub message: String,
+    // message 为 base64 编码的二进制消息
+    pub binary: bool,
+}
+
+/**
+ * 离线消息添加
+ */
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OfflineMessageCreate {
+    pub app_id: String,
+    pub user_id: String,
+    pub message_id: Option<String>,
+    pub message: String,
+    pub binary: bool,
+    // 保留时间（秒）
+    pub ttl: i64,
+}
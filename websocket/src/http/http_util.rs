@@ -1,57 +1,256 @@
-use reqwest::Client;
+use crate::props::config::Config;
+use log::{debug, info, warn};
+use reqwest::{Client, Url};
 use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-/// 发送 HTTP POST 请求
-/// - url: 请求地址
-/// - data: JSON 字符串
-/// - headers: 可选 HTTP 头部
-/// 返回 Result<Value, String>，请求失败或解析失败都会返回 Err
-pub async fn http_post(
-    url: &str,
-    data: &str,
-    headers: &[(&str, &str)],
-) -> Result<Value, String> {
-    let client = Client::new();
-
-    // 构建请求
-    let mut request = client
-        .post(url)
-        .body(data.to_string())
-        .header("Content-Type", "application/json");
-
-    for (k, v) in headers {
-        request = request.header(*k, *v);
-    }
-
-    // 发送请求
-    let resp = match request.send().await {
-        Ok(r) => {
-            println!("请求成功发送到: {}", url);
-            r
-        }
-        Err(e) => {
-            println!("请求失败啦！Error: {}", e);
-            return Err(format!("Request failed: {}", e));
-        }
-    };
-
-    // 检查 HTTP 状态
-    if resp.status().is_success() {
-        println!("HTTP 状态成功: {}", resp.status());
-        match resp.json::<Value>().await {
-            Ok(json) => {
-                println!("解析 JSON 成功: {}", json);
-                Ok(json)
+// 空闲连接保留时间
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+
+/// 共享的 HTTP 客户端（认证服务、应用回调、节点转发）
+/// - 连接池复用 keep-alive 连接，连接与请求都有超时
+/// - 按目标（scheme://host:port）熔断：连续失败达到阈值后在冷却期内直接失败，冷却期后放行一个探测请求
+#[derive(Clone)]
+pub struct HttpClient {
+    client: Client,
+    breakers: Arc<Mutex<HashMap<String, Breaker>>>,
+    breaker_failures: u32,
+    breaker_open: Duration,
+    max_retries: u32,
+    retry_interval: Duration,
+}
+
+// 单个目标的熔断状态
+#[derive(Default)]
+struct Breaker {
+    // 连续失败次数
+    failures: u32,
+    // 熔断结束时间
+    open_until: Option<Instant>,
+    // 半开状态下已放行探测请求
+    probing: bool,
+}
+
+// 单次请求的失败，NotSent 为连接失败（请求未发出），可安全重试
+enum Failure {
+    NotSent(String),
+    Failed(String),
+}
+
+// 半开状态下放行的探测请求，未记录结果就被丢弃时（如调用方取消）释放探测状态
+struct Probe<'a> {
+    breakers: &'a Mutex<HashMap<String, Breaker>>,
+    target: &'a str,
+    active: bool,
+}
+
+impl Drop for Probe<'_> {
+    fn drop(&mut self) {
+        if self.active
+            && let Some(breaker) = self.breakers.lock().unwrap().get_mut(self.target)
+        {
+            breaker.probing = false;
+        }
+    }
+}
+
+impl HttpClient {
+    pub fn new(config: &Config) -> reqwest::Result<Self> {
+        let client = Client::builder()
+            .connect_timeout(Duration::from_millis(config.http_connect_timeout_ms))
+            .timeout(Duration::from_millis(config.http_request_timeout_ms))
+            .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+            .pool_max_idle_per_host(config.http_pool_max_idle_per_host)
+            .tcp_keepalive(TCP_KEEPALIVE)
+            .build()?;
+        Ok(Self {
+            client,
+            breakers: Arc::new(Mutex::new(HashMap::new())),
+            breaker_failures: config.http_breaker_failures.max(1),
+            breaker_open: Duration::from_secs(config.http_breaker_open_secs),
+            max_retries: config.http_max_retries,
+            retry_interval: Duration::from_millis(config.http_retry_interval_ms),
+        })
+    }
+
+    /// 发送 JSON POST 请求，不重试
+    /// 2xx 返回响应体：JSON 解析为 Value，非 JSON 返回字符串，空响应返回 Null
+    pub async fn post(&self, url: &str, data: &str, headers: &[(&str, &str)]) -> Result<Value, String> {
+        match self.post_once(url, data, headers).await {
+            Ok(value) => Ok(value),
+            Err(Failure::NotSent(e) | Failure::Failed(e)) => Err(e),
+        }
+    }
+
+    /// 发送 JSON POST 请求，只在连接失败（请求未发出）时按指数退避重试
+    /// 超时与 5xx 不重试：对端可能已处理该请求，重试会重复投递
+    pub async fn post_with_retry(&self, url: &str, data: &str, headers: &[(&str, &str)]) -> Result<Value, String> {
+        let mut attempts: u32 = 0;
+        loop {
+            attempts += 1;
+            match self.post_once(url, data, headers).await {
+                Ok(value) => return Ok(value),
+                Err(Failure::NotSent(e)) if attempts <= self.max_retries => {
+                    debug!("POST {} failed (attempt {}), retrying: {}", url, attempts, e);
+                    tokio::time::sleep(self.retry_interval * 2u32.saturating_pow(attempts - 1)).await;
+                }
+                Err(Failure::NotSent(e) | Failure::Failed(e)) => return Err(e),
             }
+        }
+    }
+
+    async fn post_once(&self, url: &str, data: &str, headers: &[(&str, &str)]) -> Result<Value, Failure> {
+        let target = target(url);
+        let Some(_probe) = self.acquire(&target) else {
+            return Err(Failure::Failed(format!("Circuit open for {}", target)));
+        };
+
+        let mut request = self
+            .client
+            .post(url)
+            .body(data.to_string())
+            .header("Content-Type", "application/json");
+        for (k, v) in headers {
+            request = request.header(*k, *v);
+        }
+
+        let resp = match request.send().await {
+            Ok(resp) => resp,
             Err(e) => {
-                println!("解析 JSON 失败: {}", e);
-                Err(format!("Parse JSON failed: {}", e))
+                warn!("POST {} failed: {}", url, e);
+                self.record(&target, false);
+                let error = format!("Request failed: {}", e);
+                return Err(if e.is_connect() { Failure::NotSent(error) } else { Failure::Failed(error) });
             }
-        }
-    } else {
+        };
+
         let status = resp.status();
-        let text = resp.text().await.unwrap_or_default();
-        println!("HTTP 请求返回错误状态 {}: {}", status, text);
-        Err(format!("HTTP {}: {}", status, text))
+        // 4xx 说明目标可用，不计入熔断
+        self.record(&target, !status.is_server_error());
+        let body = resp.text().await.unwrap_or_default();
+        if status.is_success() {
+            debug!("POST {} returned {}", url, status);
+            return Ok(parse_body(&body));
+        }
+        warn!("POST {} returned {}", url, status);
+        Err(Failure::Failed(format!("HTTP {}", status)))
+    }
+
+    // 熔断期间拒绝请求；冷却期结束后只放行一个探测请求
+    fn acquire<'a>(&'a self, target: &'a str) -> Option<Probe<'a>> {
+        let mut breakers = self.breakers.lock().unwrap();
+        let mut probe = Probe { breakers: &self.breakers, target, active: false };
+        let Some(breaker) = breakers.get_mut(target) else {
+            return Some(probe);
+        };
+        match breaker.open_until {
+            None => Some(probe),
+            Some(until) if Instant::now() < until => None,
+            Some(_) if breaker.probing => None,
+            Some(_) => {
+                breaker.probing = true;
+                probe.active = true;
+                Some(probe)
+            }
+        }
+    }
+
+    fn record(&self, target: &str, success: bool) {
+        let mut breakers = self.breakers.lock().unwrap();
+        if success {
+            if breakers.remove(target).is_some_and(|b| b.open_until.is_some()) {
+                info!("Circuit closed for {}", target);
+            }
+            return;
+        }
+        let breaker = breakers.entry(target.to_string()).or_default();
+        breaker.failures += 1;
+        breaker.probing = false;
+        if breaker.failures >= self.breaker_failures {
+            if breaker.open_until.is_none() {
+                warn!("Circuit opened for {} after {} failures", target, breaker.failures);
+            }
+            breaker.open_until = Some(Instant::now() + self.breaker_open);
+        }
+    }
+}
+
+// 熔断粒度：scheme://host:port
+fn target(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|u| {
+            let host = u.host_str()?.to_string();
+            Some(format!("{}://{}:{}", u.scheme(), host, u.port_or_known_default().unwrap_or_default()))
+        })
+        .unwrap_or_else(|| url.to_string())
+}
+
+fn parse_body(body: &str) -> Value {
+    if body.trim().is_empty() {
+        return Value::Null;
+    }
+    serde_json::from_str(body).unwrap_or_else(|_| Value::String(body.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(breaker_failures: u32, breaker_open: Duration) -> HttpClient {
+        HttpClient {
+            client: Client::new(),
+            breakers: Arc::new(Mutex::new(HashMap::new())),
+            breaker_failures,
+            breaker_open,
+            max_retries: 0,
+            retry_interval: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn circuit_breaker_opens_and_probes() {
+        let http = client(2, Duration::ZERO);
+        let target = target("http://10.0.0.1:9010/api/node/push");
+        assert_eq!(target, "http://10.0.0.1:9010");
+
+        http.record(&target, false);
+        assert!(http.acquire(&target).is_some());
+        http.record(&target, false);
+        // 冷却期为 0：只放行一个探测请求
+        let probe = http.acquire(&target);
+        assert!(probe.is_some());
+        assert!(http.acquire(&target).is_none());
+        http.record(&target, true);
+        drop(probe);
+        assert!(http.acquire(&target).is_some());
+
+        let http = client(1, Duration::from_secs(60));
+        http.record(&target, false);
+        assert!(http.acquire(&target).is_none());
+    }
+
+    #[test]
+    fn dropped_probe_releases_half_open() {
+        let http = client(1, Duration::ZERO);
+        let target = target("http://10.0.0.1:9010/api/node/push");
+        http.record(&target, false);
+
+        // 探测请求未完成就被取消，下一次请求可以重新探测
+        let probe = http.acquire(&target);
+        assert!(probe.is_some());
+        assert!(http.acquire(&target).is_none());
+        drop(probe);
+        assert!(http.acquire(&target).is_some());
+    }
+
+    #[test]
+    fn parse_non_json_body() {
+        assert_eq!(parse_body(""), Value::Null);
+        assert_eq!(parse_body("ok"), Value::String("ok".to_string()));
+        assert_eq!(parse_body(r#"{"code":200}"#)["code"], 200);
     }
 }
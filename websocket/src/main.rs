@@ -11,21 +11,40 @@ mod config;
 mod props;
 mod http;
 
+use crate::http::http_util::HttpClient;
+use crate::web_socket::upstream::UpstreamDispatcher;
 use crate::web_socket::web_socket_server::{AppState, SessionManager, ws_handler};
 use actix_web::{
     App, HttpServer,
     web::{self, Data},
 };
 use sqlx::postgres::PgPoolOptions;
-use crate::config::middleware::AuthMiddleware;
+use crate::common::error::AppError;
+use crate::config::middleware::{AuthMiddleware, RequestIdMiddleware};
 use crate::config::redis_manager::RedisManager;
 use crate::db::obj::DbState;
-use crate::props::config::get_config;
+use crate::props::config::{run_reloader, NodeRoute};
+use crate::web_socket::node_route::run_node_subscriber;
+use crate::web_socket::session_control::run_control_subscriber;
+use crate::service::application_use_service::run_app_invalidation_subscriber;
+use crate::web_socket::node_registry::{self, NodeInfo};
+use crate::web_socket::shutdown::{graceful_shutdown, purge_node};
+use log::{error, info};
+use std::sync::atomic::AtomicBool;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let config = get_config().expect("TODO: panic message");
+    // .env 中的 WS_* 变量参与配置覆盖，需先加载
     dotenvy::dotenv().ok();
+    let config_handle = match props::config::init() {
+        Ok(handle) => handle,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let config = config_handle.current();
+    common::logging::init(&config.log_level);
 
     let db = PgPoolOptions::new()
         .max_connections(config.db_max_connections)
@@ -40,28 +59,97 @@ async fn main() -> std::io::Result<()> {
         .expect("redis connect failed"));
 
     let session_manager = SessionManager::new();
+    let http = match HttpClient::new(&config) {
+        Ok(http) => http,
+        Err(e) => {
+            error!("Build HTTP client failed: {}", e);
+            std::process::exit(1);
+        }
+    };
     let state = Data::new(AppState {
-        app_name: "ws-gateway".into(),
         session_manager,
         redis: redis_ws,
-        db: db.clone()
+        db: db.clone(),
+        upstream: UpstreamDispatcher::new(db.clone(), http.clone(), &config),
+        http,
+        draining: AtomicBool::new(false),
+        config: config_handle.clone(),
     });
 
+    // Redis pub/sub 路由：订阅本节点频道
+    if config.node_route == NodeRoute::Pubsub {
+        actix::spawn(run_node_subscriber(
+            state.redis.clone(),
+            state.session_manager.clone(),
+            config_handle.clone(),
+        ));
+    }
+
+    // 订阅本节点控制频道，其他节点通过它踢掉本节点上的会话
+    actix::spawn(run_control_subscriber(
+        state.redis.clone(),
+        state.session_manager.clone(),
+        config_handle.clone(),
+    ));
+
+    // SIGHUP 或配置文件变更时热加载节点列表、限流与日志级别
+    actix::spawn(run_reloader(config_handle.clone()));
+
+    // 订阅应用变更频道，清除本地应用缓存
+    actix::spawn(run_app_invalidation_subscriber(state.redis.clone()));
+
     let redis = Data::new(RedisManager::new(&config.redis_url)
         .expect("redis connect failed"));
 
+    // 清理本节点上次崩溃遗留的会话记录
+    match purge_node(&state.redis, &config.app_ip, config.port).await {
+        Ok(removed) => info!("Purged {} stale session entries left by previous run", removed),
+        Err(e) => error!("Purge stale session entries failed: {:?}", e),
+    }
 
-    HttpServer::new(move || {
+    // 注册本节点并定时心跳
+    let node_info = NodeInfo::new(config.app_ip.clone(), config.port);
+    if let Err(e) = node_registry::register(&state.redis, &node_info, config.node_ttl_secs).await {
+        error!("Register node failed: {:?}", e);
+    }
+    actix::spawn(node_registry::run_heartbeat(
+        state.redis.clone(),
+        state.session_manager.clone(),
+        node_info,
+        config_handle,
+    ));
+
+    let shutdown_state = state.clone();
+    let server = HttpServer::new(move || {
         App::new()
             .wrap(AuthMiddleware)
+            // 最外层，认证失败的请求也带 request_id
+            .wrap(RequestIdMiddleware)
             .app_data(state.clone())
             .app_data(db_state.clone())
             .app_data(redis.clone())
+            // 请求体、查询参数、路径参数解析失败时返回 JSON 错误
+            .app_data(web::JsonConfig::default().error_handler(|e, _| AppError::BadRequest(e.to_string()).into()))
+            .app_data(web::QueryConfig::default().error_handler(|e, _| AppError::BadRequest(e.to_string()).into()))
+            .app_data(web::PathConfig::default().error_handler(|e, _| AppError::BadRequest(e.to_string()).into()))
             .route("/ws", web::get().to(ws_handler))
             .configure(controller::config_services)
     })
+    // 停机信号由 graceful_shutdown 处理
+    .disable_signals()
     .bind(("0.0.0.0", config.port))?
+    .run();
+
+    actix::spawn(graceful_shutdown(server.handle(), shutdown_state));
+
+    // 监控指标单独监听，业务端口上不提供 /metrics
+    let metrics_server = HttpServer::new(|| App::new().service(controller::metrics_controller::get_metrics))
+        .workers(1)
+        .disable_signals()
+        .bind(("0.0.0.0", config.metrics_port))?
+        .run();
+    actix::spawn(metrics_server);
+
     // 启动服务器并等待
-    .run()
-    .await
+    server.await
 }
\ No newline at end of file
@@ -1,11 +1,14 @@
-use std::{env, fs, sync::Arc};
-use std::path::Path;
-use std::sync::{Mutex};
-use std::time::{Duration, Instant};
-use serde::Deserialize;
-use std::sync::LazyLock;
-
-#[derive(Deserialize, Debug, Clone)]
+use std::{env, fmt, fs, sync::Arc};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use log::{error, info, warn};
+use crate::common::logging;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Config {
     pub db_url: String,
     pub port: u16,
@@ -14,22 +17,121 @@ pub struct Config {
     pub db_max_connections: u32,
     // token过期时间
     pub token_ex: u64,
-    // 本地缓存控制参数
-    #[serde(default = "default_true")]
-    pub local_cache_enabled: bool,
-    #[serde(default = "default_cache_ttl")]
-    pub local_cache_ttl: u64,  // TTL in seconds
 
     pub node_token: String,
 
     // 设置 应用的IP
     pub app_ip: String,
 
-    // 节点通信权限
+    // 节点通信权限（可选，未配置的节点从注册中心发现，使用 node_token 通信）
     pub node_config: Option<Vec<NodeConfig>>,
 
+    // 节点注册：心跳间隔（秒）
+    #[serde(default = "default_node_heartbeat_secs")]
+    pub node_heartbeat_secs: u64,
+    // 节点注册：心跳过期时间（秒），超过即视为节点下线
+    #[serde(default = "default_node_ttl_secs")]
+    pub node_ttl_secs: u64,
+
+    // 消息确认：等待客户端 ack 的超时时间（秒），超时后重发
+    #[serde(default = "default_ack_timeout_secs")]
+    pub ack_timeout_secs: u64,
+    // 消息确认：单个连接上的最大重发次数
+    #[serde(default = "default_ack_max_redeliveries")]
+    pub ack_max_redeliveries: u32,
+    // 消息确认：未确认消息在 Redis 中的保留时间（秒），用于重连后重发
+    #[serde(default = "default_ack_pending_ttl")]
+    pub ack_pending_ttl: i64,
+
+    // 离线消息：保留时间（秒）
+    #[serde(default = "default_offline_ttl")]
+    pub offline_ttl: i64,
+    // 离线消息：每个用户最多保留条数，超出后丢弃最早的
+    #[serde(default = "default_offline_max_per_user")]
+    pub offline_max_per_user: i64,
+
+    // 心跳：服务端发送 ping 的间隔（秒）
+    #[serde(default = "default_heartbeat_interval_secs")]
+    pub heartbeat_interval_secs: u64,
+    // 心跳：客户端超过该时间（秒）无任何帧则断开
+    #[serde(default = "default_client_idle_timeout_secs")]
+    pub client_idle_timeout_secs: u64,
+
+    // 会话登记：单条会话记录的过期时间（秒），连接存活期间定时续期
+    #[serde(default = "default_session_ttl_secs")]
+    pub session_ttl_secs: u64,
+
+    // 应用信息本地缓存时间（秒），0 不缓存
+    #[serde(default = "default_app_cache_ttl_secs")]
+    pub app_cache_ttl_secs: u64,
+    // 认证服务结果在 Redis 中的缓存时间（秒），0 不缓存
+    #[serde(default = "default_auth_cache_ttl_secs")]
+    pub auth_cache_ttl_secs: u64,
+
+    // 限流：单个连接每秒消息数，0 不限制
+    #[serde(default = "default_rate_conn_messages_per_sec")]
+    pub rate_conn_messages_per_sec: u64,
+    // 限流：单个连接每秒字节数，0 不限制
+    #[serde(default = "default_rate_conn_bytes_per_sec")]
+    pub rate_conn_bytes_per_sec: u64,
+    // 限流：单个用户所有会话合计每秒消息数（Redis），0 不限制
+    #[serde(default = "default_rate_user_messages_per_sec")]
+    pub rate_user_messages_per_sec: u32,
+    // 限流：单个应用推送接口每秒请求数（Redis），0 不限制
+    #[serde(default = "default_rate_app_push_per_sec")]
+    pub rate_app_push_per_sec: u32,
+    // 限流：10 秒内超限次数达到该值的连接会被断开
+    #[serde(default = "default_rate_max_violations")]
+    pub rate_max_violations: u32,
+
+    // 日志级别，可按 target 前缀覆盖，如 info,sqlx=warn
+    #[serde(default = "default_log_level")]
+    pub log_level: String,
+
+    // Prometheus 抓取端口，/metrics 只在该端口提供，不对外暴露在业务端口上
+    #[serde(default = "default_metrics_port")]
+    pub metrics_port: u16,
+
+    // 停机时等待会话关闭的最长时间（秒）
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+
+    // 节点间消息路由方式：http | pubsub
+    #[serde(default)]
+    pub node_route: NodeRoute,
+
+    // 上行消息回调：失败重试次数
+    #[serde(default = "default_callback_max_retries")]
+    pub callback_max_retries: u32,
+    // 上行消息回调：首次重试间隔（毫秒），之后按指数退避
+    #[serde(default = "default_callback_retry_interval_ms")]
+    pub callback_retry_interval_ms: u64,
+    // 上行消息回调：每个应用的最大并发投递数
+    #[serde(default = "default_callback_max_concurrency")]
+    pub callback_max_concurrency: usize,
+
+    // HTTP 客户端：建立连接超时（毫秒）
+    #[serde(default = "default_http_connect_timeout_ms")]
+    pub http_connect_timeout_ms: u64,
+    // HTTP 客户端：整个请求的超时（毫秒）
+    #[serde(default = "default_http_request_timeout_ms")]
+    pub http_request_timeout_ms: u64,
+    // HTTP 客户端：每个目标保留的空闲连接数
+    #[serde(default = "default_http_pool_max_idle_per_host")]
+    pub http_pool_max_idle_per_host: usize,
+    // 节点转发：连接失败时的重试次数，首次重试间隔（毫秒），之后按指数退避
+    #[serde(default = "default_http_max_retries")]
+    pub http_max_retries: u32,
+    #[serde(default = "default_http_retry_interval_ms")]
+    pub http_retry_interval_ms: u64,
+    // 熔断：同一目标连续失败次数达到该值后熔断，熔断持续时间（秒）
+    #[serde(default = "default_http_breaker_failures")]
+    pub http_breaker_failures: u32,
+    #[serde(default = "default_http_breaker_open_secs")]
+    pub http_breaker_open_secs: u64,
+
 }
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NodeConfig{
     // ip
     pub ip: String,
@@ -39,38 +141,323 @@ pub struct NodeConfig{
     pub token: String,
 }
 
+/// 节点间消息路由方式
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeRoute {
+    // HTTP POST 到对端 /api/node/push，需要 node_config 中的 token
+    #[default]
+    Http,
+    // 发布到对端节点的 Redis 频道，无需 node_config
+    Pubsub,
+}
 
 // 默认值函数
-fn default_true() -> bool { true }
-fn default_cache_ttl() -> u64 { 300 } // 5 minutes default
-fn default_cache_size_limit() -> usize { 100 }
+fn default_callback_max_retries() -> u32 { 3 }
+fn default_callback_retry_interval_ms() -> u64 { 500 }
+fn default_callback_max_concurrency() -> usize { 16 }
+fn default_http_connect_timeout_ms() -> u64 { 2000 }
+fn default_http_request_timeout_ms() -> u64 { 5000 }
+fn default_http_pool_max_idle_per_host() -> usize { 32 }
+fn default_http_max_retries() -> u32 { 2 }
+fn default_http_retry_interval_ms() -> u64 { 100 }
+fn default_http_breaker_failures() -> u32 { 5 }
+fn default_http_breaker_open_secs() -> u64 { 30 }
+fn default_ack_timeout_secs() -> u64 { 10 }
+fn default_ack_max_redeliveries() -> u32 { 3 }
+fn default_ack_pending_ttl() -> i64 { 86400 }
+fn default_offline_ttl() -> i64 { 604800 } // 7 days
+fn default_offline_max_per_user() -> i64 { 100 }
+fn default_heartbeat_interval_secs() -> u64 { 5 }
+fn default_client_idle_timeout_secs() -> u64 { 30 }
+fn default_shutdown_timeout_secs() -> u64 { 10 }
+fn default_metrics_port() -> u16 { 9011 }
+fn default_log_level() -> String { "info".to_string() }
+fn default_session_ttl_secs() -> u64 { 60 }
+fn default_app_cache_ttl_secs() -> u64 { 60 }
+fn default_auth_cache_ttl_secs() -> u64 { 300 }
+fn default_rate_conn_messages_per_sec() -> u64 { 20 }
+fn default_rate_conn_bytes_per_sec() -> u64 { 1048576 } // 1 MB
+fn default_rate_user_messages_per_sec() -> u32 { 50 }
+fn default_rate_app_push_per_sec() -> u32 { 500 }
+fn default_rate_max_violations() -> u32 { 20 }
+fn default_node_heartbeat_secs() -> u64 { 5 }
+fn default_node_ttl_secs() -> u64 { 15 }
+
+
+// 环境变量前缀，如 WS_PORT、WS_DB_URL
+const ENV_PREFIX: &str = "WS_";
+// 指定配置文件路径的环境变量 / 命令行参数
+const CONFIG_PATH_ENV: &str = "WS_CONFIG";
+const CONFIG_PATH_FLAG: &str = "config";
+// 配置文件变更的检查间隔
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+// 字符串类型的字段，环境变量与命令行中的值不按 YAML 标量解析（如 node_token: 123456）
+const STRING_KEYS: [&str; 7] = ["db_url", "redis_url", "redis_ws", "node_token", "app_ip", "log_level", "node_route"];
+
+// 可热加载的字段，其余字段修改后需重启
+const RELOADABLE_KEYS: [&str; 8] = [
+    "node_config",
+    "rate_conn_messages_per_sec",
+    "rate_conn_bytes_per_sec",
+    "rate_user_messages_per_sec",
+    "rate_app_push_per_sec",
+    "rate_max_violations",
+    "offline_max_per_user",
+    "log_level",
+];
+
+// 已废弃的字段，旧配置中仍可能存在，加载时忽略并告警
+const DEPRECATED_KEYS: [&str; 3] = ["local_cache_enabled", "local_cache_ttl", "local_cache_size_limit"];
+
+/// 配置加载错误
+#[derive(Debug)]
+pub enum ConfigError {
+    // 未找到配置文件
+    NotFound(Vec<String>),
+    Read(PathBuf, std::io::Error),
+    Parse(String),
+    // 命令行参数错误
+    Args(String),
+    // 校验失败的字段
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::NotFound(paths) => write!(f, "config file not found, tried: {}", paths.join(", ")),
+            ConfigError::Read(path, e) => write!(f, "read {} failed: {}", path.display(), e),
+            ConfigError::Parse(msg) => write!(f, "parse config failed: {}", msg),
+            ConfigError::Args(msg) => write!(f, "invalid argument: {}", msg),
+            ConfigError::Invalid(errors) => write!(f, "invalid config:\n  {}", errors.join("\n  ")),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
 
-// 全局缓存
-static CONFIG_CACHE: LazyLock<Arc<Mutex<Option<(Config, Instant)>>>> = 
-    LazyLock::new(|| Arc::new(Mutex::new(None)));
+// 配置来源：文件，环境变量覆盖文件，命令行覆盖环境变量
+#[derive(Debug, Clone)]
+struct ConfigSources {
+    path: PathBuf,
+    env: Vec<(String, String)>,
+    args: Vec<(String, String)>,
+}
 
-fn get_cache() -> Arc<Mutex<Option<(Config, Instant)>>> {
-    CONFIG_CACHE.clone()
+/// 启动时加载的配置，SIGHUP 或文件变更时只重新加载 RELOADABLE_KEYS 中的字段
+pub struct ConfigHandle {
+    current: RwLock<Arc<Config>>,
+    sources: ConfigSources,
+    modified: Mutex<Option<SystemTime>>,
 }
 
-// 同步版本
-pub fn get_config() -> Result<Config, Box<dyn std::error::Error>> {
-    // 尝试从缓存获取
-    let cache = get_cache(); // 先获取 Arc
+impl ConfigHandle {
+    pub fn current(&self) -> Arc<Config> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// 重新读取配置，校验通过后替换可热加载的字段
+    pub fn reload(&self) -> Result<(), ConfigError> {
+        let next = load(&self.sources)?;
+        let current = self.current();
+        let (before, after) = (to_mapping(&current), to_mapping(&next));
+
+        let mut merged = (*current).clone();
+        let mut applied = vec![];
+        for (key, value) in after.iter() {
+            let Some(key) = key.as_str() else { continue };
+            if before.get(key) == Some(value) {
+                continue;
+            }
+            if RELOADABLE_KEYS.contains(&key) {
+                applied.push(key);
+            } else {
+                warn!("Config field {} changed, restart required to apply", key);
+            }
+        }
+        if applied.is_empty() {
+            info!("Config reloaded, nothing to apply");
+            return Ok(());
+        }
+
+        merged.node_config = next.node_config;
+        merged.rate_conn_messages_per_sec = next.rate_conn_messages_per_sec;
+        merged.rate_conn_bytes_per_sec = next.rate_conn_bytes_per_sec;
+        merged.rate_user_messages_per_sec = next.rate_user_messages_per_sec;
+        merged.rate_app_push_per_sec = next.rate_app_push_per_sec;
+        merged.rate_max_violations = next.rate_max_violations;
+        merged.offline_max_per_user = next.offline_max_per_user;
+        if merged.log_level != next.log_level {
+            logging::set_level(&next.log_level);
+        }
+        merged.log_level = next.log_level;
 
-    let mut cache_guard = cache.lock().unwrap();
-    if let Some((ref cached_config, timestamp)) = *cache_guard {
-        // 检查是否过期
-        if cached_config.local_cache_enabled &&
-            timestamp.elapsed() < Duration::from_secs(cached_config.local_cache_ttl) {
-            println!("✅ Returning config from cache");
-            return Ok(cached_config.clone());
+        *self.current.write().unwrap() = Arc::new(merged);
+        info!("Config reloaded, applied: {}", applied.join(", "));
+        Ok(())
+    }
+
+    // 配置文件修改时间是否变化
+    fn file_changed(&self) -> bool {
+        let modified = fs::metadata(&self.sources.path).and_then(|m| m.modified()).ok();
+        let mut last = self.modified.lock().unwrap();
+        if modified.is_some() && modified != *last {
+            *last = modified;
+            return true;
         }
+        false
     }
+}
 
-    drop(cache_guard); // 释放锁以便重新加载
+/// 按 文件 → 环境变量（WS_*）→ 命令行（--port 9010 / --db-url=...）的顺序加载并校验配置，只在启动时调用一次
+pub fn init() -> Result<Arc<ConfigHandle>, ConfigError> {
+    let args = parse_args(env::args().skip(1))?;
+    let env: Vec<(String, String)> = env::vars().filter(|(k, _)| k.starts_with(ENV_PREFIX)).collect();
+    let sources = ConfigSources {
+        path: find_config_file(&args, &env)?,
+        env,
+        args,
+    };
+    let config = load(&sources)?;
+    let modified = fs::metadata(&sources.path).and_then(|m| m.modified()).ok();
+    let handle = Arc::new(ConfigHandle {
+        current: RwLock::new(Arc::new(config)),
+        sources,
+        modified: Mutex::new(modified),
+    });
+    Ok(handle)
+}
+
+/// 收到 SIGHUP 或配置文件变更时重新加载，失败时保留原配置
+pub async fn run_reloader(handle: Arc<ConfigHandle>) {
+    #[cfg(unix)]
+    let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(signal) => Some(signal),
+        Err(e) => {
+            error!("Listen for SIGHUP failed: {:?}", e);
+            None
+        }
+    };
+
+    let mut interval = tokio::time::interval(WATCH_INTERVAL);
+    loop {
+        #[cfg(unix)]
+        let signalled = tokio::select! {
+            Some(_) = async { hangup.as_mut()?.recv().await } => true,
+            _ = interval.tick() => false,
+        };
+        #[cfg(not(unix))]
+        let signalled = {
+            interval.tick().await;
+            false
+        };
+
+        if !signalled && !handle.file_changed() {
+            continue;
+        }
+        info!("Reloading config on {}", if signalled { "SIGHUP" } else { "file change" });
+        if let Err(e) = handle.reload() {
+            error!("Reload config failed, keeping current config: {}", e);
+        }
+    }
+}
+
+fn load(sources: &ConfigSources) -> Result<Config, ConfigError> {
+    log::info!("Loading config from: {}", sources.path.display());
+    let content = fs::read_to_string(&sources.path).map_err(|e| ConfigError::Read(sources.path.clone(), e))?;
+    let mut mapping: Mapping = match serde_yaml::from_str(&content)
+        .map_err(|e| ConfigError::Parse(format!("{}: {}", sources.path.display(), e)))?
+    {
+        Value::Null => Mapping::new(),
+        Value::Mapping(mapping) => mapping,
+        _ => return Err(ConfigError::Parse(format!("{}: expected a mapping", sources.path.display()))),
+    };
+
+    for (name, value) in &sources.env {
+        if name == CONFIG_PATH_ENV {
+            continue;
+        }
+        let key = name[ENV_PREFIX.len()..].to_lowercase();
+        mapping.insert(Value::String(key.clone()), override_value(&key, value, name)?);
+    }
+    for (key, value) in &sources.args {
+        if key == CONFIG_PATH_FLAG {
+            continue;
+        }
+        mapping.insert(Value::String(key.clone()), override_value(key, value, &format!("--{}", key.replace('_', "-")))?);
+    }
+    for key in DEPRECATED_KEYS {
+        if mapping.remove(key).is_some() {
+            warn!("Config field {} is deprecated and ignored", key);
+        }
+    }
+
+    let config: Config = serde_yaml::from_value(Value::Mapping(mapping.clone()))
+        .map_err(|e| ConfigError::Parse(e.to_string()))?;
+
+    // 文件与命令行中的未知字段视为错误；WS_ 环境变量可能属于其他程序，忽略
+    let known = to_mapping(&config);
+    let mut errors = vec![];
+    for key in mapping.keys().filter_map(Value::as_str) {
+        if known.contains_key(key) || sources.env.iter().any(|(name, _)| name[ENV_PREFIX.len()..].eq_ignore_ascii_case(key)) {
+            continue;
+        }
+        if sources.args.iter().any(|(arg, _)| arg == key) {
+            errors.push(format!("unknown flag --{}", key.replace('_', "-")));
+        } else {
+            errors.push(format!("unknown field `{}` in {}", key, sources.path.display()));
+        }
+    }
+    errors.extend(validate(&config));
+    if !errors.is_empty() {
+        return Err(ConfigError::Invalid(errors));
+    }
+    Ok(config)
+}
+
+// 环境变量与命令行中的值按 YAML 标量解析（数字、布尔、node_config 列表），字符串字段保持原样
+fn override_value(key: &str, raw: &str, source: &str) -> Result<Value, ConfigError> {
+    if STRING_KEYS.contains(&key) {
+        return Ok(Value::String(raw.to_string()));
+    }
+    serde_yaml::from_str(raw).map_err(|e| ConfigError::Parse(format!("{}: {}", source, e)))
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Vec<(String, String)>, ConfigError> {
+    let mut parsed = vec![];
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        let Some(flag) = arg.strip_prefix("--") else {
+            return Err(ConfigError::Args(format!("unexpected argument `{}`", arg)));
+        };
+        let (name, value) = match flag.split_once('=') {
+            Some((name, value)) => (name.to_string(), value.to_string()),
+            None => match args.next_if(|next| !next.starts_with("--")) {
+                Some(value) => (flag.to_string(), value),
+                None => return Err(ConfigError::Args(format!("missing value for --{}", flag))),
+            },
+        };
+        parsed.push((name.replace('-', "_"), value));
+    }
+    Ok(parsed)
+}
+
+// 配置文件路径：--config，其次 WS_CONFIG，最后依次查找默认位置
+fn find_config_file(args: &[(String, String)], env: &[(String, String)]) -> Result<PathBuf, ConfigError> {
+    let explicit = args
+        .iter()
+        .chain(env)
+        .find(|(key, _)| key == CONFIG_PATH_FLAG || key == CONFIG_PATH_ENV)
+        .map(|(_, path)| path.clone());
+    if let Some(path) = explicit {
+        if Path::new(&path).exists() {
+            return Ok(PathBuf::from(path));
+        }
+        return Err(ConfigError::NotFound(vec![path]));
+    }
 
-    // 缓存未命中或已过期，从文件加载
     let possible_paths = vec![
         "./config.yaml".to_string(),
         env::var("CARGO_MANIFEST_DIR")
@@ -80,34 +467,78 @@ pub fn get_config() -> Result<Config, Box<dyn std::error::Error>> {
             .map(|dir| format!("{}/config.yaml", dir))
             .unwrap_or_default(),
     ];
+    possible_paths
+        .iter()
+        .find(|path| !path.is_empty() && Path::new(path).exists())
+        .map(PathBuf::from)
+        .ok_or(ConfigError::NotFound(possible_paths))
+}
 
-    // 使用引用迭代，不消耗所有权
-    for path in &possible_paths {
-        if Path::new(path).exists() {
-            println!("✅ Loading config from: {}", path);
-            let yaml_content = fs::read_to_string(path)?;
-            let config: Config = serde_yaml::from_str(&yaml_content)?;
-            println!("{:?}", config);
-
-            // 存储到缓存
-            if config.local_cache_enabled {
-                let cache = get_cache(); // 重新获取 Arc
-                let mut cache_guard = cache.lock().unwrap();
-                *cache_guard = Some((config.clone(), Instant::now()));
-            }
-
-            return Ok(config);
+fn validate(config: &Config) -> Vec<String> {
+    let mut errors = vec![];
+    let mut check = |ok: bool, msg: &str| {
+        if !ok {
+            errors.push(msg.to_string());
         }
-    }
-
-    // 现在 possible_paths 仍然可用
-    let error_msg = format!(
-        "Config file not found. Tried:\n{}",
-        possible_paths.join("\n")
+    };
+    check(config.port != 0, "port must not be 0");
+    check(
+        config.metrics_port != 0 && config.metrics_port != config.port,
+        "metrics_port must not be 0 or equal to port",
+    );
+    check(
+        config.db_url.starts_with("postgres://") || config.db_url.starts_with("postgresql://"),
+        "db_url must be a postgres:// url",
+    );
+    check(config.db_max_connections > 0, "db_max_connections must be greater than 0");
+    check(
+        config.redis_url.starts_with("redis://") || config.redis_url.starts_with("rediss://"),
+        "redis_url must be a redis:// url",
+    );
+    check(
+        config.redis_ws.starts_with("redis://") || config.redis_ws.starts_with("rediss://"),
+        "redis_ws must be a redis:// url",
     );
-    Err(error_msg.into())
+    check(!config.node_token.is_empty(), "node_token must not be empty");
+    check(config.app_ip.parse::<IpAddr>().is_ok(), "app_ip must be an IP address");
+    check(config.token_ex > 0, "token_ex must be greater than 0");
+    check(config.heartbeat_interval_secs > 0, "heartbeat_interval_secs must be greater than 0");
+    check(
+        config.client_idle_timeout_secs > config.heartbeat_interval_secs,
+        "client_idle_timeout_secs must be greater than heartbeat_interval_secs",
+    );
+    check(config.node_heartbeat_secs > 0, "node_heartbeat_secs must be greater than 0");
+    check(
+        config.node_ttl_secs > config.node_heartbeat_secs,
+        "node_ttl_secs must be greater than node_heartbeat_secs",
+    );
+    check(config.session_ttl_secs > 0, "session_ttl_secs must be greater than 0");
+    check(config.ack_timeout_secs > 0, "ack_timeout_secs must be greater than 0");
+    check(config.callback_max_concurrency > 0, "callback_max_concurrency must be greater than 0");
+    check(config.offline_max_per_user >= 0, "offline_max_per_user must not be negative");
+    check(config.http_connect_timeout_ms > 0, "http_connect_timeout_ms must be greater than 0");
+    check(
+        config.http_request_timeout_ms >= config.http_connect_timeout_ms,
+        "http_request_timeout_ms must not be less than http_connect_timeout_ms",
+    );
+    check(config.http_breaker_failures > 0, "http_breaker_failures must be greater than 0");
+    if let Err(e) = logging::validate_level(&config.log_level) {
+        errors.push(format!("log_level: {}", e));
+    }
+    for node in config.node_config.iter().flatten() {
+        if node.ip.parse::<IpAddr>().is_err() || node.port == 0 || node.token.is_empty() {
+            errors.push(format!("node_config entry {}:{} needs a valid ip, port and token", node.ip, node.port));
+        }
+    }
+    errors
 }
 
+fn to_mapping(config: &Config) -> Mapping {
+    match serde_yaml::to_value(config) {
+        Ok(Value::Mapping(mapping)) => mapping,
+        _ => Mapping::new(),
+    }
+}
 
 // 获取可执行文件所在目录
 fn get_exe_dir() -> Option<String> {
@@ -116,31 +547,69 @@ fn get_exe_dir() -> Option<String> {
         .and_then(|exe_path| exe_path.parent().map(|p| p.to_string_lossy().to_string()))
 }
 
-// 更简单的版本：始终从项目根目录加载
-// pub fn get_config_simple() -> Result<Config, Box<dyn std::error::Error>> {
-//     // 获取项目根目录（Cargo.toml所在目录）
-//     let project_root = env::var("CARGO_MANIFEST_DIR")
-//         .unwrap_or_else(|_| ".".to_string());
-//
-//     let config_path = format!("{}/config.yaml", project_root);
-//     println!("Looking for config at: {}", config_path);
-//
-//     let yaml_content = fs::read_to_string(&config_path)?;
-//     let config: Config = serde_yaml::from_str(&yaml_content)?;
-//     println!("✅ Config loaded: {:?}", config);
-//
-//     Ok(config)
-// }
-
 
 // 测试
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn sources(env: &[(&str, &str)], args: &[&str]) -> ConfigSources {
+        let env: Vec<(String, String)> = env.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let args = parse_args(args.iter().map(|a| a.to_string())).unwrap();
+        ConfigSources {
+            path: find_config_file(&args, &env).unwrap(),
+            env,
+            args,
+        }
+    }
+
     #[test]
     fn it_works() {
-        let config = get_config().expect("TODO: panic message");
-        println!("{:?}", config);
+        let config = load(&sources(&[], &[])).unwrap();
+        assert_eq!(config.port, 9010);
+        assert_eq!(config.node_token, "123456");
+        assert_eq!(config.app_ip, "127.0.0.1");
+        assert_eq!(config.token_ex, 86401);
+        assert_eq!(config.session_ttl_secs, 60);
+        assert_eq!(config.metrics_port, 9011);
+    }
+
+    #[test]
+    fn deprecated_keys_are_ignored() {
+        let path = env::temp_dir().join(format!("ws-config-deprecated-{}.yaml", std::process::id()));
+        let content = fs::read_to_string(find_config_file(&[], &[]).unwrap()).unwrap();
+        fs::write(&path, format!("{}\nlocal_cache_enabled: true\nlocal_cache_ttl: 60\nlocal_cache_size_limit: 1000\n", content)).unwrap();
+        let config = load(&sources(&[], &["--config", path.to_str().unwrap()]));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(config.unwrap().port, 9010);
+
+        assert!(load(&sources(&[], &["--local-cache-ttl", "60"])).is_ok());
+    }
+
+    #[test]
+    fn env_and_args_override_file() {
+        let config = load(&sources(
+            &[("WS_PORT", "9100"), ("WS_NODE_TOKEN", "654321"), ("WS_OTHER_TOOL", "x")],
+            &["--port", "9200", "--rate-max-violations=5"],
+        ))
+        .unwrap();
+        assert_eq!(config.port, 9200);
+        assert_eq!(config.node_token, "654321");
+        assert_eq!(config.rate_max_violations, 5);
+
+        let err = load(&sources(&[], &["--prot", "1"])).unwrap_err().to_string();
+        assert!(err.contains("unknown flag --prot"), "{}", err);
+        let err = load(&sources(&[("WS_PORT", "0"), ("WS_LOG_LEVEL", "loud")], &[])).unwrap_err().to_string();
+        assert!(err.contains("port must not be 0") && err.contains("log_level"), "{}", err);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn string_keys_cover_string_fields() {
+        let config = load(&sources(&[], &[])).unwrap();
+        for (key, value) in to_mapping(&config) {
+            if value.is_string() {
+                assert!(STRING_KEYS.contains(&key.as_str().unwrap()), "{:?}", key);
+            }
+        }
+    }
+}
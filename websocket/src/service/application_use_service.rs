@@ -1,14 +1,211 @@
+use crate::common::dto::PageVo;
+use crate::common::error::AppError;
+use crate::config::redis_manager::RedisManager;
+use crate::dao::application_use_dao;
 use crate::dao::application_use_dao::find_app_id;
-use crate::domain::application_use::ApplicationUse;
-use actix::fut::ok;
+use crate::domain::application_use::{
+    ApplicationUse, ApplicationUseCreate, ApplicationUsePageListVo, ApplicationUseQuery,
+    ApplicationUseUpdate, AuthMode, SessionPolicy,
+};
+use crate::web_socket::session_registry;
+use actix_web::web::Data;
+use futures::StreamExt;
+use log::{debug, error, info, warn};
 use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
 
+/// 应用变更广播频道，消息内容为 app_id，各节点收到后清除本地缓存
+pub const APP_INVALIDATE_CHANNEL: &str = "web:socket:app:invalidate";
+
+// 订阅断开后的重连间隔
+const RESUBSCRIBE_INTERVAL: Duration = Duration::from_secs(3);
+
+// 本地应用缓存：app_id -> (应用, 缓存时间)
+static APP_CACHE: LazyLock<Mutex<HashMap<String, (ApplicationUse, Instant)>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// 查询应用，优先使用本地缓存
 pub(crate) async fn get_app_id(
     pool: &PgPool,
     app_id: String,
+    ttl: Duration,
 ) -> Result<ApplicationUse, sqlx::Error> {
-    match find_app_id(pool, &app_id).await {
-        Ok(app_use) => Ok(app_use),
-        Err(e) => Err(e),
+    if let Some((app_use, cached_at)) = APP_CACHE.lock().unwrap().get(&app_id)
+        && cached_at.elapsed() < ttl
+    {
+        return Ok(app_use.clone());
+    }
+
+    let app_use = find_app_id(pool, &app_id).await?;
+    if !ttl.is_zero() {
+        APP_CACHE
+            .lock()
+            .unwrap()
+            .insert(app_id, (app_use.clone(), Instant::now()));
+    }
+    Ok(app_use)
+}
+
+/// 清除本地缓存的应用
+pub fn invalidate_app(app_id: &str) {
+    if APP_CACHE.lock().unwrap().remove(app_id).is_some() {
+        debug!("Application {} removed from cache", app_id);
+    }
+}
+
+/// 清除本节点缓存并通知其他节点
+async fn publish_app_invalidation(redis: &RedisManager, app_id: &str) {
+    invalidate_app(app_id);
+    if let Err(e) = redis.publish(APP_INVALIDATE_CHANNEL, app_id).await {
+        error!("Publish app invalidation for {} failed: {:?}", app_id, e);
+    }
+}
+
+/// 订阅应用变更频道，连接断开后自动重新订阅
+pub async fn run_app_invalidation_subscriber(redis: Data<RedisManager>) {
+    loop {
+        match redis.subscribe(APP_INVALIDATE_CHANNEL).await {
+            Ok(mut pubsub) => {
+                info!("Subscribed to app invalidation channel {}", APP_INVALIDATE_CHANNEL);
+                let mut messages = pubsub.on_message();
+                while let Some(msg) = messages.next().await {
+                    match msg.get_payload::<String>() {
+                        Ok(app_id) => invalidate_app(&app_id),
+                        Err(e) => warn!("Invalid payload on {}: {:?}", APP_INVALIDATE_CHANNEL, e),
+                    }
+                }
+                warn!("App invalidation channel subscription closed");
+            }
+            Err(e) => error!("Subscribe app invalidation channel failed: {:?}", e),
+        }
+        // 订阅中断期间可能错过变更通知，重新订阅前清空缓存
+        APP_CACHE.lock().unwrap().clear();
+        tokio::time::sleep(RESUBSCRIBE_INTERVAL).await;
+    }
+}
+
+pub async fn get_page(
+    pool: &PgPool,
+    redis: &RedisManager,
+    query: ApplicationUseQuery,
+) -> Result<PageVo<ApplicationUsePageListVo>, AppError> {
+    let mut page = application_use_dao::find_page(pool, query).await?;
+    let app_ids: Vec<String> = page.list.iter().map(|app| app.app_id.clone()).collect();
+    let counts = session_registry::count_sessions(redis, &app_ids).await?;
+    for app in page.list.iter_mut() {
+        app.connections = counts.get(&app.app_id).copied().unwrap_or_default();
+    }
+    Ok(page)
+}
+
+pub async fn get_app(
+    pool: &PgPool,
+    redis: &RedisManager,
+    app_id: &str,
+) -> Result<ApplicationUsePageListVo, AppError> {
+    let app = find_app_id(pool, app_id).await.map_err(not_found)?;
+    let counts = session_registry::count_sessions(redis, std::slice::from_ref(&app.app_id)).await?;
+    Ok(ApplicationUsePageListVo {
+        id: app.id,
+        connections: counts.get(&app.app_id).copied().unwrap_or_default(),
+        app_id: app.app_id,
+        app_auth_url: app.app_auth_url,
+        app_callback_message: app.app_callback_message,
+        max_sessions_per_user: app.max_sessions_per_user,
+        session_policy: app.session_policy,
+        auth_mode: app.auth_mode,
+        enabled: app.enabled,
+    })
+}
+
+/// 创建应用，返回包含密钥的完整信息（密钥只在创建和轮换时返回）
+pub async fn create_app(pool: &PgPool, app: ApplicationUseCreate) -> Result<ApplicationUse, AppError> {
+    validate_settings(app.max_sessions_per_user, &app.session_policy, &app.auth_mode)?;
+    let mut app_use = ApplicationUse {
+        id: 0,
+        app_id: Uuid::new_v4().simple().to_string(),
+        token: generate_secret(),
+        app_auth_url: app.app_auth_url,
+        app_callback_message: app.app_callback_message,
+        max_sessions_per_user: app.max_sessions_per_user,
+        session_policy: app.session_policy,
+        auth_mode: app.auth_mode,
+        enabled: true,
+    };
+    app_use.id = application_use_dao::create_app(pool, &app_use).await?;
+    Ok(app_use)
+}
+
+pub async fn update_app(
+    pool: &PgPool,
+    redis: &RedisManager,
+    app: ApplicationUseUpdate,
+) -> Result<u64, AppError> {
+    validate_settings(app.max_sessions_per_user, &app.session_policy, &app.auth_mode)?;
+    let number = found(application_use_dao::update_app(pool, &app).await?)?;
+    publish_app_invalidation(redis, &app.app_id).await;
+    Ok(number)
+}
+
+/// 轮换密钥，返回新密钥；旧密钥立即失效，jwt 模式下旧密钥签发的 token 无法再连接
+/// http 模式的认证缓存按密钥区分，轮换后旧的缓存结果不再命中
+pub async fn rotate_token(pool: &PgPool, redis: &RedisManager, app_id: &str) -> Result<String, AppError> {
+    let token = generate_secret();
+    found(application_use_dao::update_token(pool, app_id, &token).await?)?;
+    publish_app_invalidation(redis, app_id).await;
+    Ok(token)
+}
+
+/// 启用 / 停用应用，停用只拒绝新连接，已建立的连接不受影响
+pub async fn set_enabled(
+    pool: &PgPool,
+    redis: &RedisManager,
+    app_id: &str,
+    enabled: bool,
+) -> Result<u64, AppError> {
+    let number = found(application_use_dao::update_enabled(pool, app_id, enabled).await?)?;
+    publish_app_invalidation(redis, app_id).await;
+    Ok(number)
+}
+
+pub async fn delete_app(pool: &PgPool, redis: &RedisManager, app_id: &str) -> Result<u64, AppError> {
+    let number = found(application_use_dao::delete_app(pool, app_id).await?)?;
+    publish_app_invalidation(redis, app_id).await;
+    Ok(number)
+}
+
+// 更新 0 行表示应用不存在
+fn found(number: u64) -> Result<u64, AppError> {
+    if number == 0 {
+        return Err(not_found(sqlx::Error::RowNotFound));
+    }
+    Ok(number)
+}
+
+fn not_found(e: sqlx::Error) -> AppError {
+    match e {
+        sqlx::Error::RowNotFound => AppError::NotFound("应用不存在".to_string()),
+        e => e.into(),
     }
 }
+
+fn validate_settings(max_sessions: i32, session_policy: &str, auth_mode: &str) -> Result<(), AppError> {
+    if max_sessions < 0 {
+        return Err(AppError::BadRequest("max_sessions_per_user 不能小于 0".to_string()));
+    }
+    if SessionPolicy::parse(session_policy).is_none() {
+        return Err(AppError::BadRequest(format!("不支持的 session_policy: {}", session_policy)));
+    }
+    if AuthMode::parse(auth_mode).is_none() {
+        return Err(AppError::BadRequest(format!("不支持的 auth_mode: {}", auth_mode)));
+    }
+    Ok(())
+}
+
+// 64 位十六进制随机密钥
+fn generate_secret() -> String {
+    Uuid::new_v4().simple().to_string() + &Uuid::new_v4().simple().to_string()
+}
@@ -1,2 +1,3 @@
 pub mod user_service;
-pub mod application_use_service;
\ No newline at end of file
+pub mod application_use_service;
+pub mod offline_message_service;
\ No newline at end of file
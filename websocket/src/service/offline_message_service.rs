@@ -0,0 +1,30 @@
+use crate::dao::offline_message_dao;
+use crate::domain::offline_message::{OfflineMessage, OfflineMessageCreate};
+use sqlx::{PgConnection, PgPool};
+
+/// 保存离线消息，超出条数上限时丢弃最早的
+pub async fn save_offline_message(
+    pool: &PgPool,
+    message: OfflineMessageCreate,
+    max_per_user: i64,
+) -> Result<u64, sqlx::Error> {
+    let app_id = message.app_id.clone();
+    let user_id = message.user_id.clone();
+    let rows = offline_message_dao::create_offline_message(pool, message).await?;
+    offline_message_dao::trim_offline_messages(pool, &app_id, &user_id, max_per_user).await?;
+    Ok(rows)
+}
+
+/// 在事务内锁定用户的离线消息，投递成功后在同一事务内调用 delete_offline_messages 删除并提交
+pub async fn claim_offline_messages(
+    conn: &mut PgConnection,
+    app_id: &str,
+    user_id: &str,
+) -> Result<Vec<OfflineMessage>, sqlx::Error> {
+    offline_message_dao::claim_offline_messages(conn, app_id, user_id).await
+}
+
+/// 删除已投递的离线消息
+pub async fn delete_offline_messages(conn: &mut PgConnection, ids: &[i64]) -> Result<u64, sqlx::Error> {
+    offline_message_dao::delete_offline_messages(conn, ids).await
+}
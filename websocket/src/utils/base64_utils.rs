@@ -0,0 +1,13 @@
+use actix_web::web::Bytes;
+use base64::engine::general_purpose::STANDARD;
+use base64::{DecodeError, Engine};
+
+/// 二进制数据编码为 base64（JSON 中传输二进制消息）
+pub fn encode(data: &[u8]) -> String {
+    STANDARD.encode(data)
+}
+
+/// base64 解码为二进制帧数据
+pub fn decode(data: &str) -> Result<Bytes, DecodeError> {
+    STANDARD.decode(data).map(Bytes::from)
+}
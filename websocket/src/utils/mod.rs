@@ -1 +1,4 @@
-pub mod password_utils;
\ No newline at end of file
+pub mod password_utils;
+pub mod time_utils;
+pub mod base64_utils;
+pub mod token_utils;
\ No newline at end of file
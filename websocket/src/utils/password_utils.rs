@@ -16,7 +16,10 @@ pub fn hash_password(password: &str) -> Result<String, password_hash::Error> {
 
 
 pub fn verify_password(hash: &str, password: &str) -> bool {
-    let parsed_hash = PasswordHash::new(hash).unwrap();
+    // 库中的哈希格式损坏时视为校验失败
+    let Ok(parsed_hash) = PasswordHash::new(hash) else {
+        return false;
+    };
     Argon2::default()
         .verify_password(password.as_bytes(), &parsed_hash)
         .is_ok()
@@ -25,7 +28,7 @@ pub fn verify_password(hash: &str, password: &str) -> bool {
 
 #[cfg(test)]
 mod tests {
-    use crate::utils::password_utils::{hash_password, verify_password};
+    use crate::utils::password_utils::verify_password;
 
     #[test]
     fn it_works() {
@@ -0,0 +1,105 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 签名令牌中的声明，由应用使用 application_use.token 签发
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TokenClaims {
+    pub user_id: String,
+    pub app_id: String,
+    // 过期时间（秒级时间戳）
+    pub exp: u64,
+}
+
+/// 校验 HS256 JWT 的签名与过期时间，now 为秒级时间戳
+pub fn verify(token: &str, secret: &str, now: u64) -> Result<TokenClaims, String> {
+    let mut parts = token.split('.');
+    let (Some(header), Some(payload), Some(signature), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err("Malformed token".to_string());
+    };
+
+    let header: serde_json::Value = URL_SAFE_NO_PAD
+        .decode(header)
+        .ok()
+        .and_then(|h| serde_json::from_slice(&h).ok())
+        .ok_or("Invalid token header")?;
+    if header.get("alg").and_then(|a| a.as_str()) != Some("HS256") {
+        return Err("Unsupported token algorithm".to_string());
+    }
+
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|_| "Invalid token signature")?;
+    // 签名覆盖 header.payload，verify_slice 为常数时间比较
+    let signing_input = &token[..token.rfind('.').unwrap_or_default()];
+    mac(secret, signing_input)
+        .verify_slice(&signature)
+        .map_err(|_| "Invalid token signature")?;
+
+    let claims: TokenClaims = URL_SAFE_NO_PAD
+        .decode(payload)
+        .ok()
+        .and_then(|p| serde_json::from_slice(&p).ok())
+        .ok_or("Invalid token payload")?;
+    if claims.exp <= now {
+        return Err("Token expired".to_string());
+    }
+    Ok(claims)
+}
+
+/// SHA-256 十六进制摘要，用于缓存 key 中代替原始 token
+pub fn sha256_hex(data: &str) -> String {
+    Sha256::digest(data.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn mac(secret: &str, data: &str) -> HmacSha256 {
+    // HMAC 接受任意长度的密钥
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(data.as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // JWT 头部，只支持 HS256
+    const JWT_HEADER: &str = r#"{"alg":"HS256","typ":"JWT"}"#;
+
+    // 签发 HS256 JWT，令牌由应用签发，服务端只在测试中使用
+    fn sign(claims: &TokenClaims, secret: &str) -> String {
+        let header = URL_SAFE_NO_PAD.encode(JWT_HEADER);
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims).unwrap_or_default());
+        let signing_input = format!("{}.{}", header, payload);
+        let signature = URL_SAFE_NO_PAD.encode(mac(secret, &signing_input).finalize().into_bytes());
+        format!("{}.{}", signing_input, signature)
+    }
+
+    fn claims(exp: u64) -> TokenClaims {
+        TokenClaims {
+            user_id: "u1".to_string(),
+            app_id: "app".to_string(),
+            exp,
+        }
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let token = sign(&claims(2000), "secret");
+        assert_eq!(verify(&token, "secret", 1000), Ok(claims(2000)));
+        assert!(verify(&token, "other", 1000).is_err());
+        assert!(verify(&token, "secret", 2000).is_err());
+
+        let tampered = token.replacen('.', ".x", 1);
+        assert!(verify(&tampered, "secret", 1000).is_err());
+    }
+}
@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use std::collections::HashMap;
 
 #[derive(Debug, Serialize, FromRow, Deserialize)]
 pub struct MessageVO {
@@ -7,6 +8,58 @@ pub struct MessageVO {
     pub app_token: String,// app_token
     pub message: String,
     pub user_ids: Vec<String>,
+    // 是否需要客户端确认，确认前消息会被重发
+    #[serde(default)]
+    pub ack: bool,
+    // message 为 base64 编码的二进制数据，以二进制帧发送
+    #[serde(default)]
+    pub binary: bool,
+}
+
+// 按会话推送：直接发送给本节点上 session_id 为 client_id 的连接
+#[derive(Debug, Deserialize)]
+pub struct ClientPushVO {
+    pub client_id: Option<String>,
+    pub data: Option<String>,
+}
+
+// 二进制消息推送（application/octet-stream），消息体即为二进制数据
+#[derive(Debug, Deserialize)]
+pub struct BinaryMessageQuery {
+    pub app_id: String,
+    pub app_token: String,
+    // 逗号分隔的用户ID
+    pub user_ids: String,
+}
+
+// 单个用户的投递状态
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    // 已发送到本节点的在线连接
+    Delivered,
+    // 已转发到其他节点，或已存入离线消息等待重连后投递
+    Queued,
+    // 不在线（或转发失败）且离线消息保存失败
+    Offline,
+}
+
+// 消息推送结果
+#[derive(Debug, Serialize)]
+pub struct MessagePushResultVo {
+    // 需要确认时返回消息ID
+    pub message_id: Option<String>,
+    // user_id -> 投递状态
+    pub status: HashMap<String, DeliveryStatus>,
+}
+
+// 主题消息推送
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TopicMessageVO {
+    pub app_id: String,// app_id
+    pub app_token: String,// app_token
+    pub topic: String,
+    pub message: String,
 }
 
 // 节点消息转发
@@ -15,6 +68,15 @@ pub struct NodeMessageVO {
     pub app_id: String,
     pub app_token: String,
     pub message: String,
+    // 按主题转发时设置，目标节点按主题订阅投递
+    pub topic: Option<String>,
+    // 需要确认的消息ID，此时 message 为已封装的信封
+    pub message_id: Option<String>,
+    // message 为 base64 编码的二进制数据
+    pub binary: bool,
+    // 推送请求的 request_id，随转发传给目标节点
+    #[serde(default)]
+    pub request_id: Option<String>,
     pub node_to: Vec<NodeToVo>,
 }
 
@@ -34,6 +96,14 @@ pub struct NodeTo{
     pub app_id: String,
     pub app_token: String,
     pub message: String,
+    #[serde(default)]
+    pub topic: Option<String>,
+    #[serde(default)]
+    pub message_id: Option<String>,
+    #[serde(default)]
+    pub binary: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
 }
 
 impl NodeToVo {
@@ -54,6 +124,10 @@ impl NodeMessageVO {
             app_id,
             app_token,
             message,
+            topic: None,
+            message_id: None,
+            binary: false,
+            request_id: None,
             node_to: vec![],
         }
     }
@@ -1,2 +1,4 @@
 pub mod user_vo;
-pub mod message_vo;
\ No newline at end of file
+pub mod message_vo;
+pub mod presence_vo;
+pub mod session_vo;
\ No newline at end of file
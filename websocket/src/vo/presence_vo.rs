@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+
+// 在线状态查询参数，user_ids 以逗号分隔
+#[derive(Debug, Deserialize)]
+pub struct PresenceQuery {
+    pub app_id: String,
+    pub user_ids: String,
+}
+
+// 替换允许订阅 user_id 上下线事件的用户列表
+#[derive(Debug, Deserialize)]
+pub struct PresenceWatchersVo {
+    pub app_id: String,
+    pub user_id: String,
+    pub watchers: Vec<String>,
+}
+
+// 用户在线状态
+#[derive(Debug, Serialize)]
+pub struct PresenceVo {
+    pub user_id: String,
+    pub online: bool,
+    pub session_count: usize,
+    // 各节点上的会话数
+    pub nodes: Vec<PresenceNodeVo>,
+    // 最后一次上线/下线时间（毫秒）
+    pub last_seen: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PresenceNodeVo {
+    pub ip: String,
+    pub port: u16,
+    pub sessions: usize,
+}
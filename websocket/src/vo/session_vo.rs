@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+
+// 会话列表查询参数，cursor 为上一页返回的 next_cursor，首页为 0
+#[derive(Debug, Deserialize)]
+pub struct SessionQuery {
+    pub app_id: Option<String>,
+    pub user_id: Option<String>,
+    #[serde(default)]
+    pub cursor: u64,
+    #[serde(default = "default_page_size")]
+    pub page_size: usize,
+}
+
+// 强制断开参数，code 默认 4002
+// 会话不在本节点时需要 app_id 与 user_id 定位会话登记
+#[derive(Debug, Deserialize)]
+pub struct KickQuery {
+    pub code: Option<u16>,
+    pub reason: Option<String>,
+    pub app_id: Option<String>,
+    pub user_id: Option<String>,
+}
+
+// 会话列表分页，next_cursor 为 0 表示已遍历完
+#[derive(Debug, Serialize)]
+pub struct SessionPageVo {
+    pub next_cursor: u64,
+    pub list: Vec<SessionVo>,
+}
+
+// 在线会话
+#[derive(Debug, Serialize)]
+pub struct SessionVo {
+    pub session_id: String,
+    pub app_id: String,
+    pub user_id: String,
+    // 会话所在节点
+    pub node_ip: String,
+    pub node_port: u16,
+    pub remote_ip: Option<String>,
+    pub device: Option<String>,
+    // 连接建立时间（毫秒）
+    pub connected_at: u64,
+    // 收到的数据帧数与推送给客户端的消息数，每次续期时更新
+    pub messages_in: u64,
+    pub messages_out: u64,
+}
+
+fn default_page_size() -> usize { 20 }
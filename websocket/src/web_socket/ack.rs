@@ -0,0 +1,69 @@
+use crate::config::redis_manager::RedisManager;
+use crate::utils::time_utils::now_millis;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use redis::RedisResult;
+
+/// 未确认消息 key，按用户存放，field 为消息ID
+pub fn pending_key(app_id: &str, user_id: &str) -> String {
+    format!("web:socket:app_id:{}:user:id:{}:pending", app_id, user_id)
+}
+
+/// 等待确认的消息
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PendingMessage {
+    pub id: String,
+    // 下发给客户端的信封
+    pub envelope: String,
+    // 创建时间（毫秒），重连后按此顺序重发
+    pub created: u64,
+}
+
+/// 封装需要确认的消息，客户端收到后回复 {"action":"ack","id":"..."}（v1 协议为 {"type":"ack","id":"..."}）
+pub fn envelope(id: &str, message: &str) -> String {
+    json!({"type": "message", "id": id, "data": message}).to_string()
+}
+
+/// 保存未确认消息，并刷新过期时间
+pub async fn store_pending(
+    redis: &RedisManager,
+    app_id: &str,
+    user_id: &str,
+    id: &str,
+    envelope: &str,
+    ttl: i64,
+) -> RedisResult<()> {
+    let key = pending_key(app_id, user_id);
+    let pending = PendingMessage {
+        id: id.to_string(),
+        envelope: envelope.to_string(),
+        created: now_millis(),
+    };
+    let value = serde_json::to_string(&pending).unwrap_or_default();
+    redis.async_hset_expire(&key, id, &value, ttl).await
+}
+
+/// 客户端确认后删除
+pub async fn remove_pending(
+    redis: &RedisManager,
+    app_id: &str,
+    user_id: &str,
+    id: &str,
+) -> RedisResult<()> {
+    redis.async_hdel(&pending_key(app_id, user_id), id).await
+}
+
+/// 读取用户全部未确认消息，按创建时间排序
+pub async fn load_pending(
+    redis: &RedisManager,
+    app_id: &str,
+    user_id: &str,
+) -> RedisResult<Vec<PendingMessage>> {
+    let values = redis.async_hgetall(&pending_key(app_id, user_id)).await?;
+    let mut pending: Vec<PendingMessage> = values
+        .values()
+        .filter_map(|v| serde_json::from_str(v).ok())
+        .collect();
+    pending.sort_by_key(|p| p.created);
+    Ok(pending)
+}
@@ -1,10 +1,26 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize,Serialize)]
+#[derive(Debug, Deserialize,Serialize,Clone)]
 pub struct AppNode {
     pub ip: String,
     pub port: u16,
-    pub session_id: String
+    pub session_id: String,
+    // 会话记录过期时间（毫秒），由连接心跳续期；主题订阅成员中不使用
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub expires_at: u64,
+    // 连接建立时间（毫秒），超出会话数时踢掉最早的
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub connected_at: u64,
+    // 设备类型，per_device 策略下每种设备只保留一个会话
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    // 客户端地址及收发消息数，续期时更新，供管理接口查看
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_ip: Option<String>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub messages_in: u64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub messages_out: u64,
 }
 
 #[derive(Debug, Deserialize,Serialize)]
@@ -18,7 +34,13 @@ impl AppNode {
         AppNode {
             ip,
             port,
-            session_id
+            session_id,
+            expires_at: 0,
+            connected_at: 0,
+            device: None,
+            remote_ip: None,
+            messages_in: 0,
+            messages_out: 0,
         }
     }
 }
@@ -32,4 +54,8 @@ impl SessionUser {
         }
     }
     
-}
\ No newline at end of file
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
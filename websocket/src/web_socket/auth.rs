@@ -0,0 +1,185 @@
+use crate::common::error::AppError;
+use crate::config::redis_manager::RedisManager;
+use crate::domain::application_use::{ApplicationUse, AuthMode};
+use crate::http::http_util::HttpClient;
+use crate::props::config::Config;
+use crate::service::application_use_service::get_app_id;
+use crate::utils::time_utils::now_millis;
+use crate::utils::token_utils;
+use log::{debug, error, warn};
+use serde_json::{Value, json};
+use sqlx::PgPool;
+use std::fmt;
+use std::time::Duration;
+
+/// 认证通过的连接：应用信息及认证服务返回的 user_id
+#[derive(Debug)]
+pub struct Authorized {
+    pub app: ApplicationUse,
+    pub user_id: String,
+}
+
+/// 升级前认证失败的原因
+#[derive(Debug)]
+pub enum AuthError {
+    // 应用不存在
+    InvalidApp,
+    // 应用已停用
+    AppDisabled,
+    // 认证服务拒绝了 token
+    Rejected(String),
+    // 认证服务不可用或返回格式错误
+    AuthServer(String),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::InvalidApp => write!(f, "invalid app_id"),
+            AuthError::AppDisabled => write!(f, "app disabled"),
+            AuthError::Rejected(reason) => write!(f, "token rejected: {}", reason),
+            AuthError::AuthServer(reason) => write!(f, "auth server error: {}", reason),
+        }
+    }
+}
+
+impl AuthError {
+    /// 指标标签
+    pub fn reason(&self) -> &'static str {
+        match self {
+            AuthError::InvalidApp => "invalid_app",
+            AuthError::AppDisabled => "app_disabled",
+            AuthError::Rejected(_) => "rejected",
+            AuthError::AuthServer(_) => "auth_server",
+        }
+    }
+}
+
+// 拒绝升级时返回给客户端的错误，不包含认证服务返回的细节
+impl From<AuthError> for AppError {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::InvalidApp => AppError::Forbidden("Invalid app_id".to_string()),
+            AuthError::AppDisabled => AppError::Forbidden("App disabled".to_string()),
+            AuthError::Rejected(_) => AppError::Unauthorized("Token validation failed".to_string()),
+            AuthError::AuthServer(_) => AppError::BadGateway("Auth server error".to_string()),
+        }
+    }
+}
+
+/// 认证结果缓存：(应用密钥, token) 摘要 -> user_id，不保存原始 token
+/// 摘要包含应用密钥，轮换密钥后旧的缓存结果不再命中
+pub fn auth_cache_key(app_id: &str, app_secret: &str, token: &str) -> String {
+    let digest = token_utils::sha256_hex(&format!("{}:{}", app_secret, token));
+    format!("web:socket:app_id:{}:auth:{}", app_id, digest)
+}
+
+/// 查询应用并校验 token：jwt 模式本地验签，http 模式调用应用的 app_auth_url
+pub async fn authenticate(
+    db: &PgPool,
+    redis: &RedisManager,
+    http: &HttpClient,
+    config: &Config,
+    app_id: &str,
+    token: &str,
+) -> Result<Authorized, AuthError> {
+    let app = get_app_id(db, app_id.to_string(), Duration::from_secs(config.app_cache_ttl_secs)).await.map_err(|e| {
+        warn!("Application {} not found: {:?}", app_id, e);
+        AuthError::InvalidApp
+    })?;
+    debug!("Found app {}", app.app_id);
+    if !app.enabled {
+        return Err(AuthError::AppDisabled);
+    }
+
+    let user_id = match app.auth_mode() {
+        AuthMode::Jwt => verify_signed_token(&app, token)?,
+        AuthMode::Http => verify_with_auth_server(redis, http, &app, token, config.auth_cache_ttl_secs).await?,
+    };
+    Ok(Authorized { app, user_id })
+}
+
+/// 使用应用 token 作为密钥校验 HS256 JWT
+fn verify_signed_token(app: &ApplicationUse, token: &str) -> Result<String, AuthError> {
+    let claims = token_utils::verify(token, &app.token, now_millis() / 1000)
+        .map_err(AuthError::Rejected)?;
+    if claims.app_id != app.app_id {
+        return Err(AuthError::Rejected(format!("Token issued for app {}", claims.app_id)));
+    }
+    Ok(claims.user_id)
+}
+
+/// 调用认证服务校验 token，成功结果缓存到 Redis
+async fn verify_with_auth_server(
+    redis: &RedisManager,
+    http: &HttpClient,
+    app: &ApplicationUse,
+    token: &str,
+    cache_ttl: u64,
+) -> Result<String, AuthError> {
+    let cache_key = auth_cache_key(&app.app_id, &app.token, token);
+    if cache_ttl > 0 {
+        match redis.async_get_not_null(&cache_key).await {
+            Ok(user_id) if !user_id.is_empty() => return Ok(user_id),
+            Ok(_) => {}
+            // 缓存不可用时直接请求认证服务
+            Err(e) => warn!("Read auth cache failed: {:?}", e),
+        }
+    }
+
+    let data = json!({"token": token, "appToken": app.token}).to_string();
+    let response = http.post(&app.app_auth_url, &data, &[]).await.map_err(|e| {
+        error!("Auth server error: {:?}", e);
+        AuthError::AuthServer(e)
+    })?;
+
+    let Some(code) = response.get("code").and_then(Value::as_i64) else {
+        return Err(AuthError::AuthServer("Invalid response from auth server".to_string()));
+    };
+    if code != 200 {
+        return Err(AuthError::Rejected(format!("Auth server returned code {}", code)));
+    }
+    let Some(user_id) = response
+        .get("data")
+        .and_then(|d| d.get("userId"))
+        .and_then(Value::as_str)
+    else {
+        return Err(AuthError::AuthServer("Auth response has no userId".to_string()));
+    };
+
+    if cache_ttl > 0
+        && let Err(e) = redis.async_set_ex(&cache_key, user_id, cache_ttl).await
+    {
+        warn!("Write auth cache failed: {:?}", e);
+    }
+    Ok(user_id.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auth_cache_key_changes_with_secret() {
+        let key = auth_cache_key("app", "secret-1", "t1");
+        assert!(key.starts_with("web:socket:app_id:app:auth:"));
+        assert!(!key.contains("t1"));
+        assert_eq!(key, auth_cache_key("app", "secret-1", "t1"));
+        assert_ne!(key, auth_cache_key("app", "secret-2", "t1"));
+        assert_ne!(key, auth_cache_key("app", "secret-1", "t2"));
+    }
+
+    #[test]
+    fn auth_errors_map_to_status() {
+        use actix_web::ResponseError;
+        use actix_web::http::StatusCode;
+
+        let status = |e: AuthError| AppError::from(e).status_code();
+        assert_eq!(status(AuthError::InvalidApp), StatusCode::FORBIDDEN);
+        assert_eq!(status(AuthError::AppDisabled), StatusCode::FORBIDDEN);
+        assert_eq!(status(AuthError::Rejected("expired".to_string())), StatusCode::UNAUTHORIZED);
+        let error = AppError::from(AuthError::AuthServer("connection refused".to_string()));
+        assert_eq!(error.status_code(), StatusCode::BAD_GATEWAY);
+        assert_eq!(error.to_string(), "Auth server error");
+    }
+}
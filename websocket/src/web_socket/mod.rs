@@ -1,2 +1,14 @@
 pub mod web_socket_server;
-pub mod app_node;
\ No newline at end of file
+pub mod app_node;
+pub mod upstream;
+pub mod topic;
+pub mod node_route;
+pub mod ack;
+pub mod presence;
+pub mod shutdown;
+pub mod node_registry;
+pub mod session_registry;
+pub mod protocol;
+pub mod rate_limit;
+pub mod session_control;
+pub mod auth;
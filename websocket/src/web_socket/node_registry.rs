@@ -0,0 +1,110 @@
+use crate::config::redis_manager::RedisManager;
+use crate::props::config::ConfigHandle;
+use crate::utils::time_utils::now_millis;
+use crate::web_socket::shutdown;
+use crate::web_socket::web_socket_server::SessionManager;
+use actix_web::web::Data;
+use log::{error, warn};
+use redis::RedisResult;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 已注册节点集合，成员为 ip:port
+pub const NODES_KEY: &str = "web:socket:nodes";
+
+/// 节点信息 key，带 TTL，心跳续期，过期即视为节点下线
+pub fn node_info_key(ip: &str, port: u16) -> String {
+    format!("web:socket:node:{}:{}:info", ip, port)
+}
+
+pub fn node_id(ip: &str, port: u16) -> String {
+    format!("{}:{}", ip, port)
+}
+
+/// 节点注册信息
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NodeInfo {
+    pub ip: String,
+    pub port: u16,
+    pub version: String,
+    // 当前连接数
+    pub connections: usize,
+    // 启动时间（毫秒）
+    pub started_at: u64,
+    // 最后一次心跳（毫秒）
+    pub updated_at: u64,
+}
+
+impl NodeInfo {
+    pub fn new(ip: String, port: u16) -> Self {
+        let now = now_millis();
+        NodeInfo {
+            ip,
+            port,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            connections: 0,
+            started_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+/// 注册/续期节点
+pub async fn register(redis: &RedisManager, info: &NodeInfo, ttl: u64) -> RedisResult<()> {
+    let data = serde_json::to_string(info).unwrap_or_default();
+    redis
+        .async_set_ex(&node_info_key(&info.ip, info.port), &data, ttl)
+        .await?;
+    redis.async_sadd(NODES_KEY, &node_id(&info.ip, info.port)).await
+}
+
+/// 注销节点
+pub async fn deregister(redis: &RedisManager, ip: &str, port: u16) -> RedisResult<()> {
+    redis.async_del(&node_info_key(ip, port)).await?;
+    redis.async_srem(NODES_KEY, &node_id(ip, port)).await
+}
+
+/// 节点心跳是否有效
+pub async fn is_alive(redis: &RedisManager, ip: &str, port: u16) -> RedisResult<bool> {
+    redis.async_exists(&node_info_key(ip, port)).await
+}
+
+/// 列出存活节点，心跳已过期的从集合中移除
+pub async fn list_nodes(redis: &RedisManager) -> RedisResult<Vec<NodeInfo>> {
+    let mut nodes = vec![];
+    for id in redis.async_smembers(NODES_KEY).await? {
+        let Some((ip, port)) = id.rsplit_once(':') else {
+            continue;
+        };
+        let Ok(port) = port.parse::<u16>() else {
+            continue;
+        };
+        let data = redis.async_get_not_null(&node_info_key(ip, port)).await?;
+        match serde_json::from_str::<NodeInfo>(&data) {
+            Ok(info) => nodes.push(info),
+            Err(_) => {
+                warn!("Node {} heartbeat expired, removing from registry", id);
+                redis.async_srem(NODES_KEY, &id).await?;
+            }
+        }
+    }
+    Ok(nodes)
+}
+
+/// 定时心跳，上报连接数，并清理本节点超过会话 TTL 未刷新的 key 记录
+pub async fn run_heartbeat(redis: Data<RedisManager>, manager: SessionManager, mut info: NodeInfo, config: Arc<ConfigHandle>) {
+    loop {
+        let config = config.current();
+        tokio::time::sleep(Duration::from_secs(config.node_heartbeat_secs)).await;
+        info.connections = manager.count().await;
+        info.updated_at = now_millis();
+        if let Err(e) = register(&redis, &info, config.node_ttl_secs).await {
+            error!("Node heartbeat failed: {:?}", e);
+        }
+        let max_age_ms = config.session_ttl_secs * 1000;
+        if let Err(e) = shutdown::trim_tracked_keys(&redis, &info.ip, info.port, max_age_ms).await {
+            warn!("Trim tracked keys failed: {:?}", e);
+        }
+    }
+}
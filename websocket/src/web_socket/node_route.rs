@@ -0,0 +1,72 @@
+use crate::config::redis_manager::RedisManager;
+use crate::controller::message_controller::deliver_node_push;
+use crate::props::config::ConfigHandle;
+use crate::vo::message_vo::NodeTo;
+use crate::web_socket::web_socket_server::SessionManager;
+use actix_web::web::Data;
+use futures::StreamExt;
+use log::{debug, error, info, warn};
+use redis::RedisResult;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info_span, Instrument};
+
+// 订阅断开后的重连间隔
+const RESUBSCRIBE_INTERVAL: Duration = Duration::from_secs(3);
+
+/// 节点频道，按 app_ip:port 区分
+pub fn node_channel(ip: &str, port: u16) -> String {
+    format!("web:socket:node:{}:{}", ip, port)
+}
+
+/// 发布转发消息到目标节点频道
+pub async fn publish_node_push(redis: &RedisManager, data: &NodeTo) -> RedisResult<()> {
+    let payload = serde_json::to_string(data).unwrap_or_default();
+    redis
+        .publish(&node_channel(&data.node.ip, data.node.port), &payload)
+        .await?;
+    Ok(())
+}
+
+/// 订阅本节点频道并投递收到的消息，连接断开后自动重新订阅
+pub async fn run_node_subscriber(redis: Data<RedisManager>, manager: SessionManager, config: Arc<ConfigHandle>) {
+    let channel = {
+        let current = config.current();
+        node_channel(&current.app_ip, current.port)
+    };
+
+    loop {
+        match redis.subscribe(&channel).await {
+            Ok(mut pubsub) => {
+                info!("Subscribed to node channel {}", channel);
+                let mut messages = pubsub.on_message();
+                while let Some(msg) = messages.next().await {
+                    let payload: String = match msg.get_payload() {
+                        Ok(payload) => payload,
+                        Err(e) => {
+                            warn!("Invalid payload on {}: {:?}", channel, e);
+                            continue;
+                        }
+                    };
+                    match serde_json::from_str::<NodeTo>(&payload) {
+                        Ok(node_to) => {
+                            debug!("Node push received on {}", channel);
+                            let current = config.current();
+                            let span = info_span!(
+                                "node_push",
+                                request_id = node_to.request_id.as_deref().unwrap_or_default()
+                            );
+                            deliver_node_push(&node_to, &redis, &manager, &current)
+                                .instrument(span)
+                                .await;
+                        }
+                        Err(e) => warn!("Invalid node push on {}: {:?}", channel, e),
+                    }
+                }
+                warn!("Node channel {} subscription closed", channel);
+            }
+            Err(e) => error!("Subscribe node channel {} failed: {:?}", channel, e),
+        }
+        tokio::time::sleep(RESUBSCRIBE_INTERVAL).await;
+    }
+}
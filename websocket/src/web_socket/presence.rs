@@ -0,0 +1,149 @@
+use crate::config::redis_manager::RedisManager;
+use crate::controller::message_controller::forward_nodes;
+use crate::utils::time_utils::now_millis;
+use crate::vo::message_vo::NodeMessageVO;
+use crate::web_socket::topic::route_topic;
+use crate::web_socket::upstream::UpstreamMessage;
+use crate::web_socket::web_socket_server::AppState;
+use actix::spawn;
+use actix_web::web::Data;
+use log::{debug, error};
+use redis::RedisResult;
+use serde::Serialize;
+
+/// 用户最后一次上线/下线时间 key
+pub fn last_seen_key(app_id: &str, user_id: &str) -> String {
+    format!("web:socket:app_id:{}:user:id:{}:last_seen", app_id, user_id)
+}
+
+/// 客户端订阅该主题即可收到指定用户的上下线事件
+pub fn presence_topic(user_id: &str) -> String {
+    format!("presence:{}", user_id)
+}
+
+/// 允许订阅该用户上下线事件的用户集合 key（Set：user_id），由应用后台维护
+pub fn watchers_key(app_id: &str, user_id: &str) -> String {
+    format!("web:socket:app_id:{}:user:id:{}:presence_watchers", app_id, user_id)
+}
+
+/// 上下线主题中被订阅的 user_id，其他主题返回 None
+pub fn presence_target(topic: &str) -> Option<&str> {
+    topic.strip_prefix("presence:")
+}
+
+/// 是否允许 watcher 订阅 target 的上下线事件：订阅自己，或在 target 的允许列表中
+pub async fn can_watch(redis: &RedisManager, app_id: &str, watcher: &str, target: &str) -> RedisResult<bool> {
+    if watcher == target {
+        return Ok(true);
+    }
+    redis.async_sismember(&watchers_key(app_id, target), watcher).await
+}
+
+/// 替换用户的允许列表，只影响之后的订阅，已订阅的连接不受影响
+pub async fn set_watchers(redis: &RedisManager, app_id: &str, user_id: &str, watchers: &[String]) -> RedisResult<()> {
+    redis.async_replace_set(&watchers_key(app_id, user_id), watchers).await
+}
+
+/// 记录最后一次上线/下线时间
+pub async fn touch_last_seen(redis: &RedisManager, app_id: &str, user_id: &str) -> RedisResult<()> {
+    redis
+        .async_set(&last_seen_key(app_id, user_id), &now_millis().to_string())
+        .await
+}
+
+/// 上下线事件
+#[derive(Debug, Serialize)]
+pub struct PresenceEvent {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub app_id: String,
+    pub user_id: String,
+    pub online: bool,
+    pub session_count: usize,
+    pub timestamp: u64,
+}
+
+/// 应用回调信息，连接升级前认证时取得
+pub struct PresenceCallback {
+    pub app_token: String,
+    pub callback_url: String,
+}
+
+/// 发布上下线事件：投递到应用回调地址，并推送给订阅了 presence:{user_id} 的客户端
+pub fn notify(
+    state: Data<AppState>,
+    app_id: String,
+    user_id: String,
+    session_id: String,
+    online: bool,
+    session_count: usize,
+    callback: PresenceCallback,
+) {
+    let event = PresenceEvent {
+        kind: "presence",
+        app_id: app_id.clone(),
+        user_id: user_id.clone(),
+        online,
+        session_count,
+        timestamp: now_millis(),
+    };
+    let payload = serde_json::to_string(&event).unwrap_or_default();
+    debug!("Presence changed: {}", payload);
+
+    let mut message = UpstreamMessage::new(
+        app_id.clone(),
+        callback.app_token,
+        Some(user_id.clone()),
+        session_id,
+        payload.clone(),
+    );
+    message.event = "presence";
+    state.upstream.dispatch(callback.callback_url, message);
+
+    spawn(async move {
+        if let Err(e) = touch_last_seen(&state.redis, &app_id, &user_id).await {
+            error!("Update last seen of user {} failed: {:?}", user_id, e);
+        }
+
+        let config = state.config.current();
+        let topic = presence_topic(&user_id);
+        let mut node_list = NodeMessageVO::init(app_id, String::new(), payload);
+        node_list.topic = Some(topic.clone());
+        match route_topic(&state.redis, &state.session_manager, &config, &mut node_list, &topic).await {
+            Ok(_) if !node_list.node_to.is_empty() => {
+                forward_nodes(node_list, config, state.redis.clone(), state.http.clone()).await;
+            }
+            Ok(_) => {}
+            Err(e) => error!("Push presence of user {} failed: {:?}", user_id, e),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_presence_topic() {
+        assert_eq!(presence_target("presence:u1"), Some("u1"));
+        assert_eq!(presence_target("lobby"), None);
+    }
+
+    #[actix_web::test]
+    #[ignore = "requires Redis (WS_TEST_REDIS_URL)"]
+    async fn watchers_allow_presence_subscription() {
+        let url = std::env::var("WS_TEST_REDIS_URL").expect("WS_TEST_REDIS_URL is not set");
+        let redis = RedisManager::new(&url).unwrap();
+        let app_id = format!("test-{}", uuid::Uuid::new_v4().simple());
+
+        assert!(can_watch(&redis, &app_id, "u1", "u1").await.unwrap());
+        assert!(!can_watch(&redis, &app_id, "u2", "u1").await.unwrap());
+
+        set_watchers(&redis, &app_id, "u1", &["u2".to_string()]).await.unwrap();
+        assert!(can_watch(&redis, &app_id, "u2", "u1").await.unwrap());
+        assert!(!can_watch(&redis, &app_id, "u3", "u1").await.unwrap());
+
+        set_watchers(&redis, &app_id, "u1", &[]).await.unwrap();
+        assert!(!can_watch(&redis, &app_id, "u2", "u1").await.unwrap());
+    }
+}
@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// 客户端协议 v1，通过 Sec-WebSocket-Protocol 协商
+pub const PROTOCOL_V1: &str = "ws.v1";
+
+// 服务端支持的子协议，按优先级排列
+pub const SUPPORTED_PROTOCOLS: [&str; 1] = [PROTOCOL_V1];
+
+/// 连接使用的协议版本
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolVersion {
+    // 未协商子协议的旧客户端：文本消息原样投递到应用
+    Legacy,
+    // 带类型的信封
+    V1,
+}
+
+impl ProtocolVersion {
+    /// 根据 Sec-WebSocket-Protocol 请求头协商版本，客户端声明的协议都不支持时返回 None
+    pub fn negotiate(header: Option<&str>) -> Option<Self> {
+        let Some(header) = header else {
+            return Some(ProtocolVersion::Legacy);
+        };
+        header
+            .split(',')
+            .map(str::trim)
+            .any(|p| p == PROTOCOL_V1)
+            .then_some(ProtocolVersion::V1)
+    }
+}
+
+/// 主题订阅参数
+#[derive(Debug, Deserialize)]
+pub struct TopicPayload {
+    pub topic: String,
+}
+
+/// 客户端帧
+/// - {"type":"subscribe","id":"1","payload":{"topic":"lobby"}}，取消订阅同理
+/// - {"type":"publish","id":"2","payload":...}：投递到应用回调地址
+/// - {"type":"ack","id":"..."}：确认服务端消息
+/// - {"type":"ping","id":"3"}
+/// - {"type":"rpc","id":"4","payload":...}：同步调用应用回调地址，应答原样返回
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ClientFrame {
+    Subscribe {
+        id: Option<String>,
+        payload: TopicPayload,
+    },
+    Unsubscribe {
+        id: Option<String>,
+        payload: TopicPayload,
+    },
+    Publish {
+        id: Option<String>,
+        payload: Value,
+    },
+    Ack {
+        id: String,
+    },
+    Ping {
+        id: Option<String>,
+    },
+    Rpc {
+        id: String,
+        #[serde(default)]
+        payload: Value,
+    },
+}
+
+// 客户端帧支持的 type
+const CLIENT_FRAME_TYPES: [&str; 6] = ["subscribe", "unsubscribe", "publish", "ack", "ping", "rpc"];
+
+/// 错误码
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    // 不是合法的 JSON 或缺少字段
+    InvalidFrame,
+    // 未知的 type
+    UnknownType,
+    InvalidTopic,
+    // 无权订阅该主题
+    Forbidden,
+    // 超出限流
+    RateLimited,
+    RpcFailed,
+    Internal,
+}
+
+/// 服务端帧（消息下发仍使用 ack 模块的 {"type":"message"} 信封）
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ServerFrame {
+    Reply {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        payload: Value,
+    },
+    Pong {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+    },
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        code: ErrorCode,
+        message: String,
+    },
+}
+
+impl ServerFrame {
+    pub fn error(id: Option<String>, code: ErrorCode, message: impl Into<String>) -> Self {
+        ServerFrame::Error {
+            id,
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// 解析客户端帧，失败时返回可直接下发的错误帧
+pub fn parse(text: &str) -> Result<ClientFrame, ServerFrame> {
+    serde_json::from_str::<ClientFrame>(text).map_err(|e| {
+        let value = serde_json::from_str::<Value>(text).unwrap_or_default();
+        let id = value.get("id").and_then(Value::as_str).map(str::to_string);
+        match value.get("type").and_then(Value::as_str) {
+            Some(kind) if !CLIENT_FRAME_TYPES.contains(&kind) => {
+                ServerFrame::error(id, ErrorCode::UnknownType, format!("Unknown type: {}", kind))
+            }
+            _ => ServerFrame::error(id, ErrorCode::InvalidFrame, e.to_string()),
+        }
+    })
+}
+
+/// 消息内容：字符串原样使用，其他 JSON 序列化
+pub fn payload_text(payload: Value) -> String {
+    match payload {
+        Value::String(text) => text,
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiate_protocol() {
+        assert_eq!(ProtocolVersion::negotiate(None), Some(ProtocolVersion::Legacy));
+        assert_eq!(ProtocolVersion::negotiate(Some("chat, ws.v1")), Some(ProtocolVersion::V1));
+        assert_eq!(ProtocolVersion::negotiate(Some("chat")), None);
+    }
+
+    #[test]
+    fn parse_frames() {
+        let frame = parse(r#"{"type":"subscribe","id":"1","payload":{"topic":"lobby"}}"#);
+        assert!(matches!(frame, Ok(ClientFrame::Subscribe { payload, .. }) if payload.topic == "lobby"));
+
+        let frame = parse(r#"{"type":"shout","id":"2"}"#);
+        assert!(matches!(
+            frame,
+            Err(ServerFrame::Error { code: ErrorCode::UnknownType, id: Some(id), .. }) if id == "2"
+        ));
+
+        let frame = parse(r#"{"type":"ack"}"#);
+        assert!(matches!(frame, Err(ServerFrame::Error { code: ErrorCode::InvalidFrame, .. })));
+    }
+}
@@ -0,0 +1,184 @@
+use crate::config::redis_manager::RedisManager;
+use redis::{RedisResult, Script};
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+/// 统计超限次数的时间窗口，窗口内超限过多的连接会被断开
+pub const VIOLATION_WINDOW: Duration = Duration::from_secs(10);
+
+// Redis 令牌桶：按 Redis 服务器时间补充令牌，多个节点共用同一个桶
+static TOKEN_BUCKET_SCRIPT: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r#"
+        local rate = tonumber(ARGV[1])
+        local capacity = tonumber(ARGV[2])
+        local cost = tonumber(ARGV[3])
+        local time = redis.call('TIME')
+        local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+        local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'ts')
+        local tokens = tonumber(bucket[1]) or capacity
+        local ts = tonumber(bucket[2]) or now
+        tokens = math.min(capacity, tokens + math.max(0, now - ts) * rate / 1000)
+        local allowed = 0
+        if tokens >= cost then
+            tokens = tokens - cost
+            allowed = 1
+        end
+        redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'ts', now)
+        redis.call('PEXPIRE', KEYS[1], math.ceil(capacity / rate * 1000) + 1000)
+        return allowed
+        "#,
+    )
+});
+
+/// 用户级限流 key，用户的所有会话共用
+pub fn user_rate_key(app_id: &str, user_id: &str) -> String {
+    format!("web:socket:app_id:{}:user:id:{}:rate", app_id, user_id)
+}
+
+/// 应用推送接口限流 key
+pub fn app_push_rate_key(app_id: &str) -> String {
+    format!("web:socket:app_id:{}:push_rate", app_id)
+}
+
+/// 从 Redis 令牌桶取一个令牌，桶容量为每秒速率（允许 1 秒的突发），rate 为 0 不限制
+pub async fn allow(redis: &RedisManager, key: &str, rate: u32) -> RedisResult<bool> {
+    if rate == 0 {
+        return Ok(true);
+    }
+    let rate = rate.to_string();
+    let allowed: i64 = redis
+        .eval_script(&TOKEN_BUCKET_SCRIPT, &[key], &[&rate, &rate, "1"])
+        .await?;
+    Ok(allowed == 1)
+}
+
+/// 本地令牌桶（单个连接使用）
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// rate 为每秒补充的令牌数，同时作为桶容量，0 不限制
+    pub fn new(rate: u64) -> Self {
+        Self::with_capacity(rate, rate)
+    }
+
+    /// 桶容量与补充速率不同（容量不小于单次可能取走的令牌数）
+    pub fn with_capacity(rate: u64, capacity: u64) -> Self {
+        TokenBucket {
+            rate: rate as f64,
+            capacity: capacity as f64,
+            tokens: capacity as f64,
+            last: Instant::now(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn try_take(&mut self, cost: u64) -> bool {
+        if !self.has(cost) {
+            return false;
+        }
+        self.take(cost);
+        true
+    }
+
+    // 补充令牌后判断是否足够，不扣减
+    fn has(&mut self, cost: u64) -> bool {
+        if self.rate == 0.0 {
+            return true;
+        }
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last = now;
+        self.tokens >= cost as f64
+    }
+
+    fn take(&mut self, cost: u64) {
+        if self.rate != 0.0 {
+            self.tokens -= cost as f64;
+        }
+    }
+}
+
+/// 单个连接的限流：消息数与字节数，并统计窗口内的超限次数
+#[derive(Debug)]
+pub struct ConnectionLimiter {
+    messages: TokenBucket,
+    bytes: TokenBucket,
+    violations: u32,
+    window_start: Instant,
+}
+
+impl ConnectionLimiter {
+    /// 字节桶容量不小于 max_message_size，否则速率低于单条消息上限时大消息永远无法通过
+    pub fn new(messages_per_sec: u64, bytes_per_sec: u64, max_message_size: u64) -> Self {
+        ConnectionLimiter {
+            messages: TokenBucket::new(messages_per_sec),
+            bytes: TokenBucket::with_capacity(bytes_per_sec, bytes_per_sec.max(max_message_size)),
+            violations: 0,
+            window_start: Instant::now(),
+        }
+    }
+
+    /// 收到一条 len 字节的消息，两个桶都有令牌才放行；被拒绝时两个桶都不扣减
+    pub fn allow(&mut self, len: usize) -> bool {
+        let len = len as u64;
+        if !self.messages.has(1) || !self.bytes.has(len) {
+            return false;
+        }
+        self.messages.take(1);
+        self.bytes.take(len);
+        true
+    }
+
+    /// 记录一次超限，返回当前窗口内的超限次数
+    pub fn record_violation(&mut self) -> u32 {
+        if self.window_start.elapsed() > VIOLATION_WINDOW {
+            self.window_start = Instant::now();
+            self.violations = 0;
+        }
+        self.violations += 1;
+        self.violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_bucket_limits_burst() {
+        let mut bucket = TokenBucket::new(3);
+        assert!(bucket.try_take(1));
+        assert!(bucket.try_take(2));
+        assert!(!bucket.try_take(1));
+
+        let mut unlimited = TokenBucket::new(0);
+        assert!((0..1000).all(|_| unlimited.try_take(1)));
+    }
+
+    #[test]
+    fn connection_limiter_counts_bytes() {
+        let mut limiter = ConnectionLimiter::new(10, 100, 100);
+        assert!(limiter.allow(80));
+        assert!(!limiter.allow(80));
+        assert_eq!(limiter.record_violation(), 1);
+        assert_eq!(limiter.record_violation(), 2);
+    }
+
+    #[test]
+    fn connection_limiter_allows_large_frame() {
+        // 速率低于单条消息上限时，一条满长度的消息仍可通过
+        let mut limiter = ConnectionLimiter::new(2, 1024, 65_536);
+        assert!(limiter.allow(65_536));
+        // 字节桶拒绝时不扣减消息令牌
+        assert!(!limiter.allow(65_536));
+        assert!(limiter.messages.tokens >= 1.0);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::config::redis_manager::RedisManager;
+use crate::props::config::{Config, ConfigHandle};
+use crate::web_socket::app_node::AppNode;
+use crate::web_socket::node_registry;
+use crate::web_socket::web_socket_server::SessionManager;
+use actix_web::web::Data;
+use actix_web_actors::ws::CloseCode;
+use futures::StreamExt;
+use log::{debug, error, info, warn};
+use redis::RedisResult;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 被同一用户的新会话顶替时的关闭码
+pub const CLOSE_LOGGED_IN_ELSEWHERE: u16 = 4001;
+/// 被管理接口强制断开时的默认关闭码
+pub const CLOSE_KICKED: u16 = 4002;
+
+// WebSocket 关闭帧中原因的最大字节数（控制帧 125 字节减去 2 字节关闭码）
+const MAX_CLOSE_REASON_LEN: usize = 123;
+
+// 订阅断开后的重连间隔
+const RESUBSCRIBE_INTERVAL: Duration = Duration::from_secs(3);
+
+/// 会话控制频道，其他节点通过它断开本节点上的会话
+pub fn control_channel(ip: &str, port: u16) -> String {
+    format!("web:socket:node:{}:{}:control", ip, port)
+}
+
+/// 断开会话指令
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KickCommand {
+    pub session_id: String,
+    pub code: u16,
+    pub reason: String,
+}
+
+/// 断开指定会话：本节点直接断开，其他节点通过控制频道通知
+/// 返回 false 表示会话不在本节点，或所在节点没有订阅控制频道（已下线）
+pub async fn kick(
+    redis: &RedisManager,
+    manager: &SessionManager,
+    config: &Config,
+    node: &AppNode,
+    code: u16,
+    reason: &str,
+) -> RedisResult<bool> {
+    if node.ip == config.app_ip && node.port == config.port {
+        return Ok(manager
+            .disconnect(&node.session_id, CloseCode::from(code), reason)
+            .await);
+    }
+
+    let command = KickCommand {
+        session_id: node.session_id.clone(),
+        code,
+        reason: reason.to_string(),
+    };
+    let payload = serde_json::to_string(&command).unwrap_or_default();
+    let receivers = redis
+        .publish(&control_channel(&node.ip, node.port), &payload)
+        .await?;
+    Ok(receivers > 0)
+}
+
+/// 不知道会话所在节点时，把断开指令广播给其他所有存活节点，会话所在节点收到后断开
+/// 返回收到指令的节点数
+pub async fn broadcast_kick(
+    redis: &RedisManager,
+    config: &Config,
+    session_id: &str,
+    code: u16,
+    reason: &str,
+) -> RedisResult<usize> {
+    let command = KickCommand {
+        session_id: session_id.to_string(),
+        code,
+        reason: reason.to_string(),
+    };
+    let payload = serde_json::to_string(&command).unwrap_or_default();
+    let mut receivers = 0;
+    for node in node_registry::list_nodes(redis).await? {
+        if node.ip == config.app_ip && node.port == config.port {
+            continue;
+        }
+        if redis.publish(&control_channel(&node.ip, node.port), &payload).await? > 0 {
+            receivers += 1;
+        }
+    }
+    Ok(receivers)
+}
+
+/// 截断关闭原因，保证不超过关闭帧允许的 123 字节且不截断多字节字符
+pub fn truncate_reason(reason: &str) -> &str {
+    if reason.len() <= MAX_CLOSE_REASON_LEN {
+        return reason;
+    }
+    let mut end = MAX_CLOSE_REASON_LEN;
+    while !reason.is_char_boundary(end) {
+        end -= 1;
+    }
+    &reason[..end]
+}
+
+/// 订阅本节点控制频道，连接断开后自动重新订阅
+pub async fn run_control_subscriber(redis: Data<RedisManager>, manager: SessionManager, config: Arc<ConfigHandle>) {
+    let config = config.current();
+    let channel = control_channel(&config.app_ip, config.port);
+
+    loop {
+        match redis.subscribe(&channel).await {
+            Ok(mut pubsub) => {
+                info!("Subscribed to control channel {}", channel);
+                let mut messages = pubsub.on_message();
+                while let Some(msg) = messages.next().await {
+                    let payload: String = match msg.get_payload() {
+                        Ok(payload) => payload,
+                        Err(e) => {
+                            warn!("Invalid payload on {}: {:?}", channel, e);
+                            continue;
+                        }
+                    };
+                    match serde_json::from_str::<KickCommand>(&payload) {
+                        Ok(command) => {
+                            debug!("Kick session {}: {}", command.session_id, command.reason);
+                            manager
+                                .disconnect(&command.session_id, CloseCode::from(command.code), &command.reason)
+                                .await;
+                        }
+                        Err(e) => warn!("Invalid control command on {}: {:?}", channel, e),
+                    }
+                }
+                warn!("Control channel {} subscription closed", channel);
+            }
+            Err(e) => error!("Subscribe control channel {} failed: {:?}", channel, e),
+        }
+        tokio::time::sleep(RESUBSCRIBE_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_long_reason() {
+        assert_eq!(truncate_reason("kicked"), "kicked");
+        let ascii = "a".repeat(200);
+        assert_eq!(truncate_reason(&ascii).len(), MAX_CLOSE_REASON_LEN);
+        // 每个汉字 3 字节，截断在字符边界上
+        let chinese = "踢".repeat(50);
+        let truncated = truncate_reason(&chinese);
+        assert_eq!(truncated.len(), 123);
+        assert_eq!(truncate_reason(&format!("a{}", chinese)).len(), 121);
+    }
+}
@@ -0,0 +1,614 @@
+use crate::config::redis_manager::RedisManager;
+use crate::domain::application_use::SessionPolicy;
+use crate::utils::time_utils::now_millis;
+use crate::web_socket::app_node::{AppNode, SessionUser};
+use redis::{RedisResult, Script};
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+// 登记会话，按会话数上限与策略决定拒绝或踢掉哪些会话；顺带删除旧版本的 String 会话 key
+// KEYS: 用户会话 Hash, 应用会话索引 ZSet, 旧版本会话 key
+// ARGV: session_id, AppNode, ttl, 上限, 策略, 设备类型, 当前时间（毫秒）, 过期时间（毫秒）
+// 返回 {-1} 表示拒绝，否则返回 {有效会话数, 被踢掉的 AppNode...}
+static REGISTER_SCRIPT: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r#"
+        local max = tonumber(ARGV[4])
+        local policy = ARGV[5]
+        local device = ARGV[6]
+        local now = tonumber(ARGV[7])
+
+        if redis.call('TYPE', KEYS[3]).ok == 'string' then
+            redis.call('DEL', KEYS[3])
+        end
+
+        local live = {}
+        local fields = redis.call('HGETALL', KEYS[1])
+        for i = 1, #fields, 2 do
+            local ok, node = pcall(cjson.decode, fields[i + 1])
+            if ok and (tonumber(node.expires_at) or 0) > now then
+                table.insert(live, {id = fields[i], raw = fields[i + 1], node = node})
+            else
+                redis.call('HDEL', KEYS[1], fields[i])
+                redis.call('ZREM', KEYS[2], fields[i])
+            end
+        end
+
+        local victims = {}
+        local remaining = {}
+        for _, s in ipairs(live) do
+            if policy == 'per_device' and device ~= '' and s.node.device == device then
+                table.insert(victims, s)
+            else
+                table.insert(remaining, s)
+            end
+        end
+        if max > 0 and #remaining >= max then
+            if policy == 'reject' then
+                return {-1}
+            end
+            table.sort(remaining, function(a, b)
+                return (tonumber(a.node.connected_at) or 0) < (tonumber(b.node.connected_at) or 0)
+            end)
+            for i = 1, #remaining - max + 1 do
+                table.insert(victims, remaining[i])
+            end
+        end
+
+        local result = {0}
+        local evicted = {}
+        for _, s in ipairs(victims) do
+            redis.call('HDEL', KEYS[1], s.id)
+            redis.call('ZREM', KEYS[2], s.id)
+            evicted[s.id] = true
+            table.insert(result, s.raw)
+        end
+        -- 有效会话数：未被踢掉的有效会话加上本会话，不含已过期的记录
+        local count = 1
+        for _, s in ipairs(live) do
+            if not evicted[s.id] and s.id ~= ARGV[1] then
+                count = count + 1
+            end
+        end
+        redis.call('HSET', KEYS[1], ARGV[1], ARGV[2])
+        redis.call('EXPIRE', KEYS[1], ARGV[3])
+        redis.call('ZADD', KEYS[2], ARGV[8], ARGV[1])
+        redis.call('ZREMRANGEBYSCORE', KEYS[2], '-inf', now)
+        redis.call('EXPIRE', KEYS[2], ARGV[3])
+        result[1] = count
+        return result
+        "#,
+    )
+});
+
+// 续期会话：仅当会话仍在登记中时更新；记录不存在时不写入任何 key，被踢掉或清理的会话不会被重新写入
+// KEYS: 用户会话 Hash, 应用会话索引 ZSet
+// ARGV: session_id, AppNode, ttl, 过期时间（毫秒）
+static REFRESH_SCRIPT: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r#"
+        if redis.call('HEXISTS', KEYS[1], ARGV[1]) == 0 then
+            return 0
+        end
+        redis.call('HSET', KEYS[1], ARGV[1], ARGV[2])
+        redis.call('EXPIRE', KEYS[1], ARGV[3])
+        redis.call('ZADD', KEYS[2], ARGV[4], ARGV[1])
+        redis.call('EXPIRE', KEYS[2], ARGV[3])
+        return 1
+        "#,
+    )
+});
+
+// 删除会话并返回剩余的有效会话数，过期记录顺带删除（HLEN 会把过期记录也算作在线）
+// KEYS: 用户会话 Hash, 应用会话索引 ZSet
+// ARGV: session_id, 当前时间（毫秒）
+static UNREGISTER_SCRIPT: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r#"
+        local now = tonumber(ARGV[2])
+        redis.call('HDEL', KEYS[1], ARGV[1])
+        redis.call('ZREM', KEYS[2], ARGV[1])
+        local count = 0
+        local fields = redis.call('HGETALL', KEYS[1])
+        for i = 1, #fields, 2 do
+            local ok, node = pcall(cjson.decode, fields[i + 1])
+            if ok and (tonumber(node.expires_at) or 0) > now then
+                count = count + 1
+            else
+                redis.call('HDEL', KEYS[1], fields[i])
+                redis.call('ZREM', KEYS[2], fields[i])
+            end
+        end
+        return count
+        "#,
+    )
+});
+
+// 删除旧版本的会话 key（String：SessionUser JSON），其他类型不动
+static DELETE_LEGACY_SCRIPT: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r#"
+        if redis.call('TYPE', KEYS[1]).ok == 'string' then
+            return redis.call('DEL', KEYS[1])
+        end
+        return 0
+        "#,
+    )
+});
+
+// 仅当记录未被续期（值未变化）时删除，避免误删刚续期的会话
+static REMOVE_IF_UNCHANGED_SCRIPT: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r#"
+        if redis.call('HGET', KEYS[1], ARGV[1]) == ARGV[2] then
+            redis.call('ZREM', KEYS[2], ARGV[1])
+            return redis.call('HDEL', KEYS[1], ARGV[1])
+        end
+        return 0
+        "#,
+    )
+});
+
+/// 用户会话登记 key（Hash：session_id -> AppNode）
+pub fn session_key(app_id: &str, user_id: &str) -> String {
+    format!("web:socket:app_id:{}:user:id:{}:sessions", app_id, user_id)
+}
+
+/// 旧版本的用户会话 key（String：SessionUser JSON），已不再读写，登记会话与节点清理时删除
+pub fn legacy_session_key(app_id: &str, user_id: &str) -> String {
+    format!("web:socket:app_id:{}:user:id:{}", app_id, user_id)
+}
+
+/// 应用会话索引 key（ZSet：session_id -> 过期时间（毫秒）），统计应用连接数时无需扫描会话 key
+pub fn app_sessions_key(app_id: &str) -> String {
+    format!("web:socket:app_id:{}:connections", app_id)
+}
+
+/// 会话数上限，max 为 0 不限制
+#[derive(Debug, Clone, Copy)]
+pub struct SessionLimit {
+    pub max: i32,
+    pub policy: SessionPolicy,
+}
+
+/// 登记结果
+pub enum Registration {
+    // 已登记，返回当前会话数及被踢掉的会话
+    Registered { count: usize, evicted: Vec<AppNode> },
+    // reject 策略下超出上限
+    Rejected,
+}
+
+/// 登记会话，超出上限时按策略拒绝或踢掉旧会话（同一脚本内完成，多节点并发登记不会超限）
+pub async fn register(
+    redis: &RedisManager,
+    app_id: &str,
+    user_id: &str,
+    mut node: AppNode,
+    ttl: u64,
+    limit: &SessionLimit,
+) -> RedisResult<Registration> {
+    let now = now_millis();
+    node.expires_at = now + ttl * 1000;
+    if node.connected_at == 0 {
+        node.connected_at = now;
+    }
+    let data = serde_json::to_string(&node).unwrap_or_default();
+    let result: Vec<String> = redis
+        .eval_script(
+            &REGISTER_SCRIPT,
+            &[
+                &session_key(app_id, user_id),
+                &app_sessions_key(app_id),
+                &legacy_session_key(app_id, user_id),
+            ],
+            &[
+                &node.session_id,
+                &data,
+                &ttl.to_string(),
+                &limit.max.to_string(),
+                limit.policy.as_str(),
+                node.device.as_deref().unwrap_or_default(),
+                &now.to_string(),
+                &node.expires_at.to_string(),
+            ],
+        )
+        .await?;
+
+    let count = result.first().and_then(|c| c.parse::<i64>().ok()).unwrap_or_default();
+    if count < 0 {
+        return Ok(Registration::Rejected);
+    }
+    let evicted = result[1..]
+        .iter()
+        .filter_map(|raw| serde_json::from_str::<AppNode>(raw).ok())
+        .collect();
+    Ok(Registration::Registered {
+        count: count as usize,
+        evicted,
+    })
+}
+
+/// 续期会话，返回 false 表示会话已不在登记中（被踢掉或已清理）
+pub async fn refresh(
+    redis: &RedisManager,
+    app_id: &str,
+    user_id: &str,
+    mut node: AppNode,
+    ttl: u64,
+) -> RedisResult<bool> {
+    node.expires_at = now_millis() + ttl * 1000;
+    let data = serde_json::to_string(&node).unwrap_or_default();
+    let refreshed: i64 = redis
+        .eval_script(
+            &REFRESH_SCRIPT,
+            &[&session_key(app_id, user_id), &app_sessions_key(app_id)],
+            &[&node.session_id, &data, &ttl.to_string(), &node.expires_at.to_string()],
+        )
+        .await?;
+    Ok(refreshed == 1)
+}
+
+/// 删除会话，返回该用户剩余的有效会话数
+pub async fn unregister(
+    redis: &RedisManager,
+    app_id: &str,
+    user_id: &str,
+    session_id: &str,
+) -> RedisResult<usize> {
+    redis
+        .eval_script(
+            &UNREGISTER_SCRIPT,
+            &[&session_key(app_id, user_id), &app_sessions_key(app_id)],
+            &[session_id, &now_millis().to_string()],
+        )
+        .await
+}
+
+/// 读取用户全部有效会话，过期记录顺带删除
+pub async fn list(redis: &RedisManager, app_id: &str, user_id: &str) -> RedisResult<SessionUser> {
+    let mut users = list_many(redis, app_id, std::slice::from_ref(&user_id.to_string())).await?;
+    Ok(users.pop().unwrap_or_else(|| SessionUser::new(vec![])))
+}
+
+/// 批量读取多个用户的有效会话（一次 pipeline 往返），结果与 user_ids 顺序一致
+pub async fn list_many(redis: &RedisManager, app_id: &str, user_ids: &[String]) -> RedisResult<Vec<SessionUser>> {
+    let keys: Vec<String> = user_ids.iter().map(|user_id| session_key(app_id, user_id)).collect();
+    let app_key = app_sessions_key(app_id);
+    let now = now_millis();
+    let mut users = Vec::with_capacity(keys.len());
+    for (key, sessions) in keys.iter().zip(redis.async_hgetall_many(&keys).await?) {
+        let mut nodes = vec![];
+        for (session_id, data) in sessions {
+            match serde_json::from_str::<AppNode>(&data) {
+                Ok(node) if node.expires_at > now => nodes.push(node),
+                _ => {
+                    redis
+                        .eval_script::<i64>(&REMOVE_IF_UNCHANGED_SCRIPT, &[key, &app_key], &[&session_id, &data])
+                        .await?;
+                }
+            }
+        }
+        users.push(SessionUser::new(nodes));
+    }
+    Ok(users)
+}
+
+/// 删除指定 key 中属于 ip:port 节点的会话（节点下线清理），返回删除数量
+/// 升级前记录的旧版本 String 会话 key 直接删除
+pub async fn remove_node_sessions(
+    redis: &RedisManager,
+    key: &str,
+    ip: &str,
+    port: u16,
+) -> RedisResult<usize> {
+    let Some((app_id, _)) = parse_session_key(key) else {
+        redis.eval_script::<i64>(&DELETE_LEGACY_SCRIPT, &[key], &[]).await?;
+        return Ok(0);
+    };
+    let app_key = app_sessions_key(app_id);
+    let mut removed = 0;
+    for (session_id, data) in redis.async_hgetall(key).await? {
+        let is_node = serde_json::from_str::<AppNode>(&data)
+            .is_ok_and(|node| node.ip == ip && node.port == port);
+        if is_node {
+            removed += redis
+                .eval_script::<usize>(&REMOVE_IF_UNCHANGED_SCRIPT, &[key, &app_key], &[&session_id, &data])
+                .await?;
+        }
+    }
+    Ok(removed)
+}
+
+/// 集群内的一个有效会话
+#[derive(Debug)]
+pub struct LiveSession {
+    pub app_id: String,
+    pub user_id: String,
+    pub node: AppNode,
+}
+
+/// 分页遍历会话登记：cursor 为上一页返回的游标（首页为 0），返回下一页游标（0 表示结束）
+/// 每页扫描约 count 个用户的会话 key，页内会话数不固定，可能为空页
+pub async fn list_sessions_page(
+    redis: &RedisManager,
+    app_id: Option<&str>,
+    user_id: Option<&str>,
+    cursor: u64,
+    count: usize,
+) -> RedisResult<(u64, Vec<LiveSession>)> {
+    // 指定用户时只有一个 key，无需扫描
+    if let (Some(app_id), Some(user_id)) = (app_id, user_id) {
+        let sessions = live_sessions(redis, &[session_key(app_id, user_id)]).await?;
+        return Ok((0, sessions));
+    }
+    let (next, keys) = redis.async_scan_page(&scan_pattern(app_id, user_id), cursor, count).await?;
+    Ok((next, live_sessions(redis, &keys).await?))
+}
+
+fn scan_pattern(app_id: Option<&str>, user_id: Option<&str>) -> String {
+    session_key(
+        &app_id.map(escape_pattern).unwrap_or_else(|| "*".to_string()),
+        &user_id.map(escape_pattern).unwrap_or_else(|| "*".to_string()),
+    )
+}
+
+// 读取会话 key 中的有效会话，按连接时间排序
+async fn live_sessions(redis: &RedisManager, keys: &[String]) -> RedisResult<Vec<LiveSession>> {
+    let now = now_millis();
+    let mut sessions = vec![];
+    for (key, hash) in keys.iter().zip(redis.async_hgetall_many(keys).await?) {
+        let Some((app_id, user_id)) = parse_session_key(key) else {
+            continue;
+        };
+        for data in hash.values() {
+            if let Ok(node) = serde_json::from_str::<AppNode>(data)
+                && node.expires_at > now
+            {
+                sessions.push(LiveSession {
+                    app_id: app_id.to_string(),
+                    user_id: user_id.to_string(),
+                    node,
+                });
+            }
+        }
+    }
+    sessions.sort_by_key(|s| s.node.connected_at);
+    Ok(sessions)
+}
+
+/// 统计各应用的有效会话数（全部节点）：每个应用一次 ZCOUNT（一次 pipeline 往返），不扫描会话 key
+pub async fn count_sessions(redis: &RedisManager, app_ids: &[String]) -> RedisResult<HashMap<String, usize>> {
+    let keys: Vec<String> = app_ids.iter().map(|app_id| app_sessions_key(app_id)).collect();
+    let counts = redis.async_zcount_many(&keys, &format!("({}", now_millis()), "+inf").await?;
+    Ok(app_ids.iter().cloned().zip(counts).collect())
+}
+
+// 从会话登记 key 中解析 app_id 与 user_id
+fn parse_session_key(key: &str) -> Option<(&str, &str)> {
+    key.strip_prefix("web:socket:app_id:")?
+        .strip_suffix(":sessions")?
+        .split_once(":user:id:")
+}
+
+// 转义 SCAN 模式中的通配符
+fn escape_pattern(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    // 需要 Redis 的测试默认忽略：设置 WS_TEST_REDIS_URL（如 redis://127.0.0.1:6379/15）后用 --ignored 运行
+    fn test_redis() -> RedisManager {
+        let url = std::env::var("WS_TEST_REDIS_URL").expect("WS_TEST_REDIS_URL is not set");
+        RedisManager::new(&url).unwrap()
+    }
+
+    fn test_app() -> String {
+        format!("test-{}", Uuid::new_v4().simple())
+    }
+
+    fn unlimited() -> SessionLimit {
+        SessionLimit { max: 0, policy: SessionPolicy::EvictOldest }
+    }
+
+    fn node(session_id: &str) -> AppNode {
+        AppNode::new("127.0.0.1".to_string(), 9010, session_id.to_string())
+    }
+
+    fn device_node(session_id: &str, connected_at: u64, device: &str) -> AppNode {
+        let mut node = node(session_id);
+        node.connected_at = connected_at;
+        node.device = Some(device.to_string());
+        node
+    }
+
+    // 登记并返回 (会话数, 被踢掉的 session_id)，被拒绝时返回 None
+    async fn try_register(redis: &RedisManager, app_id: &str, node: AppNode, max: i32, policy: SessionPolicy) -> Option<(usize, Vec<String>)> {
+        let limit = SessionLimit { max, policy };
+        match register(redis, app_id, "u1", node, 60, &limit).await.unwrap() {
+            Registration::Registered { count, evicted } => Some((count, evicted.into_iter().map(|n| n.session_id).collect())),
+            Registration::Rejected => None,
+        }
+    }
+
+    async fn session_ids(redis: &RedisManager, app_id: &str) -> Vec<String> {
+        let mut ids: Vec<String> = list(redis, app_id, "u1").await.unwrap().nodes.into_iter().map(|n| n.session_id).collect();
+        ids.sort();
+        ids
+    }
+
+    // 与 REGISTER_SCRIPT 中的选择逻辑一致（修改脚本时同步修改）：返回被踢掉的 session_id，reject 超限时返回 None
+    fn select_victims(live: &[AppNode], device: &str, limit: &SessionLimit) -> Option<Vec<String>> {
+        let (mut victims, mut remaining): (Vec<&AppNode>, Vec<&AppNode>) = live.iter().partition(|node| {
+            limit.policy == SessionPolicy::PerDevice && !device.is_empty() && node.device.as_deref() == Some(device)
+        });
+        let max = limit.max.max(0) as usize;
+        if max > 0 && remaining.len() >= max {
+            if limit.policy == SessionPolicy::Reject {
+                return None;
+            }
+            remaining.sort_by_key(|node| node.connected_at);
+            victims.extend(&remaining[..remaining.len() - max + 1]);
+        }
+        Some(victims.into_iter().map(|node| node.session_id.clone()).collect())
+    }
+
+    #[test]
+    fn session_policy_selects_victims() {
+        let live = [device_node("a", 2, "ios"), device_node("b", 1, "web"), device_node("c", 3, "ios")];
+        let limit = |max, policy| SessionLimit { max, policy };
+
+        // 不限制或未达到上限
+        assert_eq!(select_victims(&live, "ios", &limit(0, SessionPolicy::Reject)), Some(vec![]));
+        assert_eq!(select_victims(&live, "ios", &limit(4, SessionPolicy::EvictOldest)), Some(vec![]));
+
+        // reject：达到上限后拒绝
+        assert_eq!(select_victims(&live, "ios", &limit(3, SessionPolicy::Reject)), None);
+
+        // evict_oldest：踢掉最早连接的会话，为新会话腾出一个位置
+        assert_eq!(select_victims(&live, "ios", &limit(3, SessionPolicy::EvictOldest)), Some(vec!["b".to_string()]));
+        assert_eq!(
+            select_victims(&live, "ios", &limit(2, SessionPolicy::EvictOldest)),
+            Some(vec!["b".to_string(), "a".to_string()])
+        );
+
+        // per_device：同设备的会话都被踢掉，未指定设备时不按设备踢
+        assert_eq!(
+            select_victims(&live, "ios", &limit(0, SessionPolicy::PerDevice)),
+            Some(vec!["a".to_string(), "c".to_string()])
+        );
+        assert_eq!(select_victims(&live, "", &limit(0, SessionPolicy::PerDevice)), Some(vec![]));
+
+        // per_device 且超出上限：其他设备中最早的会话也被踢掉
+        assert_eq!(
+            select_victims(&live, "android", &limit(3, SessionPolicy::PerDevice)),
+            Some(vec!["b".to_string()])
+        );
+    }
+
+    #[actix_web::test]
+    #[ignore = "requires Redis (WS_TEST_REDIS_URL)"]
+    async fn register_applies_session_policy() {
+        let redis = test_redis();
+
+        // 不限制
+        let app = test_app();
+        for (i, id) in ["a", "b", "c"].iter().enumerate() {
+            let registered = try_register(&redis, &app, device_node(id, i as u64 + 1, "ios"), 0, SessionPolicy::Reject).await;
+            assert_eq!(registered, Some((i + 1, vec![])));
+        }
+
+        // reject：达到上限后拒绝，已有会话不变
+        let app = test_app();
+        assert_eq!(try_register(&redis, &app, device_node("a", 1, "ios"), 1, SessionPolicy::Reject).await, Some((1, vec![])));
+        assert_eq!(try_register(&redis, &app, device_node("b", 2, "ios"), 1, SessionPolicy::Reject).await, None);
+        assert_eq!(session_ids(&redis, &app).await, ["a"]);
+
+        // evict_oldest：踢掉最早连接的会话
+        let app = test_app();
+        try_register(&redis, &app, device_node("a", 2, "ios"), 2, SessionPolicy::EvictOldest).await;
+        try_register(&redis, &app, device_node("b", 1, "web"), 2, SessionPolicy::EvictOldest).await;
+        let registered = try_register(&redis, &app, device_node("c", 3, "ios"), 2, SessionPolicy::EvictOldest).await;
+        assert_eq!(registered, Some((2, vec!["b".to_string()])));
+        assert_eq!(session_ids(&redis, &app).await, ["a", "c"]);
+
+        // per_device：同设备的旧会话被踢掉，其他设备不受影响
+        let app = test_app();
+        try_register(&redis, &app, device_node("a", 1, "ios"), 0, SessionPolicy::PerDevice).await;
+        try_register(&redis, &app, device_node("b", 2, "web"), 0, SessionPolicy::PerDevice).await;
+        let registered = try_register(&redis, &app, device_node("c", 3, "ios"), 0, SessionPolicy::PerDevice).await;
+        assert_eq!(registered, Some((2, vec!["a".to_string()])));
+        assert_eq!(session_ids(&redis, &app).await, ["b", "c"]);
+
+        // per_device 且超出上限：其他设备中最早的会话被踢掉
+        let registered = try_register(&redis, &app, device_node("d", 4, "android"), 2, SessionPolicy::PerDevice).await;
+        assert_eq!(registered, Some((2, vec!["b".to_string()])));
+        assert_eq!(session_ids(&redis, &app).await, ["c", "d"]);
+    }
+
+    #[actix_web::test]
+    #[ignore = "requires Redis (WS_TEST_REDIS_URL)"]
+    async fn refresh_skips_missing_sessions() {
+        let redis = test_redis();
+        let app = test_app();
+
+        // 未登记（或已被踢掉）的会话续期不写入记录
+        assert!(!refresh(&redis, &app, "u1", node("a"), 60).await.unwrap());
+        assert!(session_ids(&redis, &app).await.is_empty());
+        assert_eq!(count_sessions(&redis, std::slice::from_ref(&app)).await.unwrap()[&app], 0);
+
+        try_register(&redis, &app, node("a"), 0, SessionPolicy::Reject).await;
+        let mut refreshed = node("a");
+        refreshed.messages_in = 5;
+        assert!(refresh(&redis, &app, "u1", refreshed, 60).await.unwrap());
+        assert_eq!(list(&redis, &app, "u1").await.unwrap().nodes[0].messages_in, 5);
+
+        unregister(&redis, &app, "u1", "a").await.unwrap();
+        assert!(!refresh(&redis, &app, "u1", node("a"), 60).await.unwrap());
+        assert!(session_ids(&redis, &app).await.is_empty());
+    }
+
+    #[actix_web::test]
+    #[ignore = "requires Redis (WS_TEST_REDIS_URL)"]
+    async fn session_count_excludes_expired_entries() {
+        let redis = test_redis();
+        let app = test_app();
+        let key = session_key(&app, "u1");
+        let legacy_key = legacy_session_key(&app, "u1");
+        redis.async_set(&legacy_key, r#"{"nodes":[]}"#).await.unwrap();
+        let mut expired = node("old");
+        expired.expires_at = now_millis() - 1;
+        redis.async_hset(&key, "old", &serde_json::to_string(&expired).unwrap()).await.unwrap();
+
+        // 过期记录不算在线，登记后旧版本 key 被删除
+        assert_eq!(try_register(&redis, &app, node("a"), 0, SessionPolicy::Reject).await, Some((1, vec![])));
+        assert!(!redis.async_exists(&legacy_key).await.unwrap());
+
+        redis.async_hset(&key, "old", &serde_json::to_string(&expired).unwrap()).await.unwrap();
+        assert_eq!(unregister(&redis, &app, "u1", "a").await.unwrap(), 0);
+        assert!(!redis.async_exists(&key).await.unwrap());
+    }
+
+    #[actix_web::test]
+    #[ignore = "requires Redis (WS_TEST_REDIS_URL)"]
+    async fn count_sessions_by_app() {
+        let redis = test_redis();
+        let (app_a, app_b) = (test_app(), test_app());
+        for (app_id, user_id, session_id) in [(&app_a, "u1", "s1"), (&app_a, "u2", "s2"), (&app_b, "u1", "s3")] {
+            register(&redis, app_id, user_id, node(session_id), 60, &unlimited()).await.unwrap();
+        }
+        let apps = vec![app_a.clone(), app_b.clone(), test_app()];
+        let counts = count_sessions(&redis, &apps).await.unwrap();
+        assert_eq!(counts[&app_a], 2);
+        assert_eq!(counts[&app_b], 1);
+        assert_eq!(counts[&apps[2]], 0);
+
+        // 注销与节点清理都会从计数中移除
+        unregister(&redis, &app_a, "u1", "s1").await.unwrap();
+        remove_node_sessions(&redis, &session_key(&app_b, "u1"), "127.0.0.1", 9010).await.unwrap();
+        let counts = count_sessions(&redis, &apps).await.unwrap();
+        assert_eq!(counts[&app_a], 1);
+        assert_eq!(counts[&app_b], 0);
+
+        unregister(&redis, &app_a, "u2", "s2").await.unwrap();
+    }
+
+    #[test]
+    fn parse_key() {
+        let key = session_key("app", "u:1");
+        assert_eq!(parse_session_key(&key), Some(("app", "u:1")));
+        assert_eq!(parse_session_key("web:socket:node:1"), None);
+        assert_eq!(escape_pattern("a*b?"), "a\\*b\\?");
+    }
+}
@@ -0,0 +1,132 @@
+use crate::config::redis_manager::RedisManager;
+use crate::utils::time_utils::now_millis;
+use crate::web_socket::app_node::AppNode;
+use crate::web_socket::node_registry;
+use crate::web_socket::session_registry::remove_node_sessions;
+use crate::web_socket::web_socket_server::{AppState, SessionManager};
+use actix_web::dev::ServerHandle;
+use actix_web::web::Data;
+use actix_web_actors::ws::CloseCode;
+use log::{error, info, warn};
+use redis::RedisResult;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+// 等待会话关闭时的轮询间隔
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// 本节点写入过的用户会话 key，有序集合，分数为最后一次登记或续期的时间（毫秒）
+pub fn node_user_keys(ip: &str, port: u16) -> String {
+    format!("web:socket:node:{}:{}:user_keys", ip, port)
+}
+
+/// 本节点写入过的主题订阅 key，有序集合，分数同上
+pub fn node_topic_keys(ip: &str, port: u16) -> String {
+    format!("web:socket:node:{}:{}:topic_keys", ip, port)
+}
+
+/// 记录本节点写入的用户会话 key，停机或重启时据此清理；会话续期时一并刷新
+pub async fn track_user_key(redis: &RedisManager, node: &AppNode, key: &str) -> RedisResult<()> {
+    redis.async_zadd(&node_user_keys(&node.ip, node.port), key, now_millis()).await
+}
+
+/// 记录本节点写入的主题订阅 key；会话续期时一并刷新
+pub async fn track_topic_key(redis: &RedisManager, node: &AppNode, key: &str) -> RedisResult<()> {
+    redis.async_zadd(&node_topic_keys(&node.ip, node.port), key, now_millis()).await
+}
+
+/// 删除超过 max_age_ms 未刷新的记录：对应会话已断开或登记已过期，不再需要停机时清理
+pub async fn trim_tracked_keys(redis: &RedisManager, ip: &str, port: u16, max_age_ms: u64) -> RedisResult<usize> {
+    let before = now_millis().saturating_sub(max_age_ms);
+    let users = redis.async_zrem_below(&node_user_keys(ip, port), before).await?;
+    let topics = redis.async_zrem_below(&node_topic_keys(ip, port), before).await?;
+    Ok(users + topics)
+}
+
+/// 删除本节点（ip:port）在 Redis 中留下的全部会话与主题订阅
+/// 启动时调用可清理上次崩溃遗留的记录，停机时调用可清理未正常关闭的会话
+pub async fn purge_node(redis: &RedisManager, ip: &str, port: u16) -> RedisResult<usize> {
+    let is_local = |node: &AppNode| node.ip == ip && node.port == port;
+    let mut removed = 0;
+
+    let user_keys = node_user_keys(ip, port);
+    for key in redis.async_zmembers(&user_keys).await? {
+        removed += remove_node_sessions(redis, &key, ip, port).await?;
+    }
+    redis.async_del(&user_keys).await?;
+
+    let topic_keys = node_topic_keys(ip, port);
+    for key in redis.async_zmembers(&topic_keys).await? {
+        for member in redis.async_smembers(&key).await? {
+            if serde_json::from_str::<AppNode>(&member).is_ok_and(|node| is_local(&node)) {
+                redis.async_srem(&key, &member).await?;
+            }
+        }
+    }
+    redis.async_del(&topic_keys).await?;
+
+    Ok(removed)
+}
+
+/// 等待 SIGINT / SIGTERM
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Listen for ctrl-c failed: {:?}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("Listen for SIGTERM failed: {:?}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+/// 优雅停机：通知客户端重连到其他节点，等待会话关闭，清理 Redis 后停止 HTTP 服务
+pub async fn graceful_shutdown(server: ServerHandle, state: Data<AppState>) {
+    shutdown_signal().await;
+    let config = state.config.current();
+    info!("Shutdown signal received, draining sessions");
+    // 停机期间就绪探针失败，不再接收新流量
+    state.draining.store(true, Ordering::Relaxed);
+
+    let manager: &SessionManager = &state.session_manager;
+    manager
+        .disconnect_all(CloseCode::Restart, "server shutting down, reconnect elsewhere")
+        .await;
+
+    let deadline = Instant::now() + Duration::from_secs(config.shutdown_timeout_secs);
+    while manager.count().await > 0 && Instant::now() < deadline {
+        tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+    }
+    let remaining = manager.count().await;
+    if remaining > 0 {
+        warn!("{} sessions still open after drain timeout", remaining);
+    }
+
+    match purge_node(&state.redis, &config.app_ip, config.port).await {
+        Ok(removed) => info!("Removed {} stale session entries of this node", removed),
+        Err(e) => error!("Purge node sessions failed: {:?}", e),
+    }
+    if let Err(e) = node_registry::deregister(&state.redis, &config.app_ip, config.port).await {
+        error!("Deregister node failed: {:?}", e);
+    }
+
+    server.stop(true).await;
+}
@@ -0,0 +1,142 @@
+use crate::config::redis_manager::RedisManager;
+use crate::props::config::Config;
+use crate::vo::message_vo::{NodeMessageVO, NodeToVo};
+use crate::web_socket::app_node::AppNode;
+use crate::web_socket::shutdown::track_topic_key;
+use crate::web_socket::web_socket_server::SessionManager;
+use log::{debug, warn};
+use redis::RedisResult;
+
+// 主题名最大长度
+const MAX_TOPIC_LEN: usize = 128;
+
+/// 主题订阅成员 key，与用户会话 key 放在同一前缀下
+pub fn topic_key(app_id: &str, topic: &str) -> String {
+    format!("web:socket:app_id:{}:topic:{}", app_id, topic)
+}
+
+/// 主题名校验：非空、不超长、不含空白字符
+pub fn is_valid_topic(topic: &str) -> bool {
+    !topic.is_empty()
+        && topic.len() <= MAX_TOPIC_LEN
+        && !topic.chars().any(char::is_whitespace)
+}
+
+/// 订阅主题，成员为会话所在的 AppNode
+pub async fn subscribe(
+    redis: &RedisManager,
+    app_id: &str,
+    topic: &str,
+    node: &AppNode,
+) -> RedisResult<()> {
+    let key = topic_key(app_id, topic);
+    redis.async_sadd(&key, &member(node)).await?;
+    track_topic_key(redis, node, &key).await
+}
+
+/// 取消订阅
+pub async fn unsubscribe(
+    redis: &RedisManager,
+    app_id: &str,
+    topic: &str,
+    node: &AppNode,
+) -> RedisResult<()> {
+    redis
+        .async_srem(&topic_key(app_id, topic), &member(node))
+        .await
+}
+
+/// 向主题订阅者投递：本节点上的会话直接发送，其他节点的汇总为转发列表
+pub async fn route_topic(
+    redis: &RedisManager,
+    manager: &SessionManager,
+    config: &Config,
+    node_list: &mut NodeMessageVO,
+    topic: &str,
+) -> RedisResult<usize> {
+    let mut delivered = 0;
+    for node in members(redis, &node_list.app_id, topic).await? {
+        if node.ip == config.app_ip && node.port == config.port {
+            if deliver(redis, manager, &node_list.app_id, topic, &node, &node_list.message).await {
+                delivered += 1;
+            }
+            continue;
+        }
+
+        let url = format!("http://{}:{}/api/node/push", node.ip, node.port);
+        if !node_list.node_to.iter().any(|n| n.base_url == url) {
+            node_list
+                .node_to
+                .push(NodeToVo::new(url, vec![], node.ip.clone(), node.port));
+        }
+    }
+    Ok(delivered)
+}
+
+/// 只投递本节点上的主题订阅者（节点转发时使用）
+pub async fn push_local(
+    redis: &RedisManager,
+    manager: &SessionManager,
+    config: &Config,
+    app_id: &str,
+    topic: &str,
+    message: &str,
+) -> RedisResult<usize> {
+    let mut delivered = 0;
+    for node in members(redis, app_id, topic).await? {
+        if node.ip == config.app_ip
+            && node.port == config.port
+            && deliver(redis, manager, app_id, topic, &node, message).await
+        {
+            delivered += 1;
+        }
+    }
+    Ok(delivered)
+}
+
+// 读取主题全部订阅成员，无法解析的成员直接丢弃
+async fn members(redis: &RedisManager, app_id: &str, topic: &str) -> RedisResult<Vec<AppNode>> {
+    let members = redis.async_smembers(&topic_key(app_id, topic)).await?;
+    Ok(members
+        .iter()
+        .filter_map(|m| serde_json::from_str::<AppNode>(m).ok())
+        .collect())
+}
+
+// 发送给本节点的会话，会话已不存在时清理订阅成员
+async fn deliver(
+    redis: &RedisManager,
+    manager: &SessionManager,
+    app_id: &str,
+    topic: &str,
+    node: &AppNode,
+    message: &str,
+) -> bool {
+    if manager.send_message(&node.session_id, message, None).await {
+        return true;
+    }
+
+    debug!("Removing stale subscriber {} from topic {}", node.session_id, topic);
+    if let Err(e) = unsubscribe(redis, app_id, topic, node).await {
+        warn!("Remove stale subscriber failed: {:?}", e);
+    }
+    false
+}
+
+fn member(node: &AppNode) -> String {
+    serde_json::to_string(node).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topic_name_validation() {
+        assert!(is_valid_topic("order:123"));
+        assert!(is_valid_topic("lobby"));
+        assert!(!is_valid_topic(""));
+        assert!(!is_valid_topic("has space"));
+        assert!(!is_valid_topic(&"a".repeat(MAX_TOPIC_LEN + 1)));
+    }
+}
@@ -0,0 +1,171 @@
+use crate::dao::callback_dead_letter_dao::create_dead_letter;
+use crate::domain::callback_dead_letter::CallbackDeadLetterCreate;
+use crate::http::http_util::HttpClient;
+use crate::props::config::Config;
+use crate::utils::time_utils::now_millis;
+use actix::spawn;
+use log::{debug, error, warn};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+/// 客户端上行消息，POST 到应用的 app_callback_message 地址
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UpstreamMessage {
+    // 事件类型：message（客户端消息）| presence（上下线）| rpc（同步调用）
+    pub event: &'static str,
+    pub app_id: String,
+    pub app_token: String,
+    pub user_id: Option<String>,
+    pub session_id: String,
+    pub message: String,
+    // 二进制消息为 base64，文本消息不输出该字段
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<&'static str>,
+    // rpc 请求ID
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    // 毫秒时间戳
+    pub timestamp: u64,
+}
+
+impl UpstreamMessage {
+    pub fn new(
+        app_id: String,
+        app_token: String,
+        user_id: Option<String>,
+        session_id: String,
+        message: String,
+    ) -> Self {
+        UpstreamMessage {
+            event: "message",
+            app_id,
+            app_token,
+            user_id,
+            session_id,
+            message,
+            encoding: None,
+            request_id: None,
+            timestamp: now_millis(),
+        }
+    }
+}
+
+/// 上行消息投递器
+/// - 每个应用独立的并发上限，一个应用的回调变慢不会拖住其他应用
+/// - 失败按指数退避重试，重试耗尽后写入 callback_dead_letter
+#[derive(Clone)]
+pub struct UpstreamDispatcher {
+    db: PgPool,
+    http: HttpClient,
+    app_limits: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>,
+    max_retries: u32,
+    retry_interval: Duration,
+    max_concurrency: usize,
+}
+
+impl UpstreamDispatcher {
+    pub fn new(db: PgPool, http: HttpClient, config: &Config) -> Self {
+        Self {
+            db,
+            http,
+            app_limits: Arc::new(Mutex::new(HashMap::new())),
+            max_retries: config.callback_max_retries,
+            retry_interval: Duration::from_millis(config.callback_retry_interval_ms),
+            max_concurrency: config.callback_max_concurrency.max(1),
+        }
+    }
+
+    /// 异步投递，不阻塞调用方（WsConn 的消息处理）
+    pub fn dispatch(&self, callback_url: String, message: UpstreamMessage) {
+        if callback_url.is_empty() {
+            debug!("App {} has no callback url, dropping upstream message", message.app_id);
+            return;
+        }
+        let dispatcher = self.clone();
+        spawn(async move {
+            dispatcher.deliver(callback_url, message).await;
+        });
+    }
+
+    /// 同步调用应用回调地址（rpc），不重试，返回应用的应答
+    pub async fn call(&self, callback_url: &str, message: UpstreamMessage) -> Result<Value, String> {
+        if callback_url.is_empty() {
+            return Err("App has no callback url".to_string());
+        }
+        let limit = self.app_limit(&message.app_id);
+        let _permit = limit.acquire_owned().await.ok();
+
+        let data = serde_json::to_string(&message).map_err(|e| e.to_string())?;
+        self.http.post(callback_url, &data, &[]).await
+    }
+
+    // 获取应用的并发信号量
+    fn app_limit(&self, app_id: &str) -> Arc<Semaphore> {
+        let mut limits = self.app_limits.lock().unwrap();
+        limits
+            .entry(app_id.to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.max_concurrency)))
+            .clone()
+    }
+
+    async fn deliver(&self, callback_url: String, message: UpstreamMessage) {
+        let limit = self.app_limit(&message.app_id);
+        // 信号量不会被关闭，acquire 只会成功
+        let _permit = limit.acquire_owned().await.ok();
+
+        let data = match serde_json::to_string(&message) {
+            Ok(data) => data,
+            Err(e) => {
+                error!("Serialize upstream message failed: {:?}", e);
+                return;
+            }
+        };
+
+        let mut attempts: u32 = 0;
+        let last_error = loop {
+            attempts += 1;
+            match self.http.post(&callback_url, &data, &[]).await {
+                Ok(_) => {
+                    debug!(
+                        "Upstream message of session {} delivered to {}",
+                        message.session_id, callback_url
+                    );
+                    return;
+                }
+                Err(e) => {
+                    warn!(
+                        "Upstream delivery to {} failed (attempt {}): {}",
+                        callback_url, attempts, e
+                    );
+                    if attempts > self.max_retries {
+                        break e;
+                    }
+                    tokio::time::sleep(self.retry_interval * 2u32.saturating_pow(attempts - 1)).await;
+                }
+            }
+        };
+
+        error!(
+            "Upstream delivery to {} gave up after {} attempts, writing dead letter",
+            callback_url, attempts
+        );
+        let dead_letter = CallbackDeadLetterCreate {
+            app_id: message.app_id,
+            user_id: message.user_id,
+            session_id: message.session_id,
+            callback_url,
+            message: message.message,
+            attempts: attempts as i32,
+            last_error,
+        };
+        if let Err(e) = create_dead_letter(&self.db, dead_letter).await {
+            error!("Write callback dead letter failed: {:?}", e);
+        }
+    }
+}
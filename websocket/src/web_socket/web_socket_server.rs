@@ -1,47 +1,137 @@
+use crate::common::error::AppError;
+use crate::common::metrics;
 use crate::config::redis_manager::RedisManager;
-use crate::http::http_util::http_post;
-use crate::props::config::get_config;
-use crate::service::application_use_service::get_app_id;
-use crate::web_socket::app_node::{AppNode, SessionUser};
+use crate::http::http_util::HttpClient;
+use crate::props::config::{Config, ConfigHandle};
+use crate::service::offline_message_service::{claim_offline_messages, delete_offline_messages};
+use crate::utils::base64_utils;
+use crate::utils::time_utils::now_millis;
+use crate::web_socket::app_node::AppNode;
+use crate::web_socket::presence::PresenceCallback;
+use crate::web_socket::protocol::{ClientFrame, ErrorCode, ProtocolVersion, SUPPORTED_PROTOCOLS, ServerFrame};
+use crate::web_socket::rate_limit::ConnectionLimiter;
+use crate::web_socket::session_registry::{Registration, SessionLimit};
+use crate::web_socket::{ack, auth, presence, protocol, rate_limit, session_control, session_registry, shutdown, topic};
+use crate::web_socket::upstream::{UpstreamDispatcher, UpstreamMessage};
 use actix::{
-    Actor, ActorContext, Addr, AsyncContext, Handler, Message, Running, StreamHandler, spawn,
+    Actor, ActorContext, ActorFutureExt, Addr, AsyncContext, Handler, Message, Running,
+    StreamHandler, WrapFuture,
 };
-use actix_web::web::Data;
+use actix_web::web::{Bytes, BytesMut, Data};
+use actix_web::http::header;
 use actix_web::{Error, HttpRequest, HttpResponse, web};
+use actix_http::ws::Item;
 use actix_web_actors::ws;
 use log::{debug, error, info, warn};
 use serde::Deserialize;
-use serde_json::Value;
-use sqlx::PgPool;
-use std::collections::HashMap;
+use serde_json::{Value, json};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
+use tracing::Instrument;
 use uuid::Uuid;
 
 pub struct AppState {
-    pub(crate) app_name: String,
     pub(crate) session_manager: SessionManager,
     pub(crate) redis: Data<RedisManager>,
     pub(crate) db: PgPool,
+    pub(crate) upstream: UpstreamDispatcher,
+    // 共享 HTTP 客户端
+    pub(crate) http: HttpClient,
+    // 排空模式：就绪探针返回失败，已建立的连接不受影响
+    pub(crate) draining: AtomicBool,
+    // 启动时加载的配置，可热加载部分字段
+    pub(crate) config: Arc<ConfigHandle>,
 }
 
 #[derive(Deserialize)]
 pub struct WsQuery {
     token: Option<String>,
     app_id: Option<String>,
-    user_id: Option<String>,
+    // 设备类型（ios、android、web 等），per_device 策略使用
+    device: Option<String>,
+}
+
+// 等待用户级限流检查的数据帧上限，超出按限流处理
+const MAX_QUEUED_FRAMES: usize = 64;
+// 每个会话本地等待确认（定时重发）的消息上限
+const MAX_PENDING_ACKS: usize = 256;
+// 分片消息拼接后的最大长度，与单帧上限（ws 编解码器默认 64KB）一致
+const MAX_MESSAGE_SIZE: usize = 65_536;
+
+// 分片消息的拼接缓冲
+struct Fragments {
+    binary: bool,
+    data: BytesMut,
+}
+
+// 客户端数据帧
+enum ClientData {
+    Text(String),
+    Binary(Bytes),
+}
+
+impl ClientData {
+    fn len(&self) -> usize {
+        match self {
+            ClientData::Text(text) => text.len(),
+            ClientData::Binary(data) => data.len(),
+        }
+    }
 }
 
 pub struct WsConn {
     state: Data<AppState>,
-    #[allow(dead_code)]
-    token: Option<String>,
-    app_id: Option<String>,
+    app_id: String,
     session_id: String,
-    #[allow(dead_code)]
-    client_id: Option<String>,
-    user_id: Option<String>,
+    // 升级前由认证服务返回，客户端无法伪造
+    user_id: String,
+    app_token: String,
+    callback_url: String,
+    // 应用配置的会话数上限
+    session_limit: SessionLimit,
+    // 已订阅的主题
+    topics: HashSet<String>,
+    // 等待客户端确认的消息ID -> 已重发次数
+    pending: HashMap<String, u32>,
+    ack_timeout: Duration,
+    ack_max_redeliveries: u32,
+    // 心跳：最后一次收到客户端帧的时间
+    last_heartbeat: Instant,
+    heartbeat_interval: Duration,
+    idle_timeout: Duration,
+    // 会话登记记录的过期时间，连接存活期间定时续期
+    session_ttl: u64,
+    // 首次登记已完成，此前不续期，避免把尚未登记的新连接当作已被清理
+    registered: Arc<AtomicBool>,
+    // 连接建立时间（毫秒）与设备类型，写入会话登记
+    connected_at: u64,
+    device: Option<String>,
+    // 客户端地址，收到的数据帧数与推送给客户端的消息数
+    remote_ip: Option<String>,
+    messages_in: u64,
+    messages_out: u64,
+    // 邮箱中尚未处理的下行消息数
+    mailbox: Arc<AtomicI64>,
+    // 本应用的收发消息计数，连接建立时取出，收发消息时不再按标签查找
+    messages_in_total: Arc<AtomicU64>,
+    messages_out_total: Arc<AtomicU64>,
+    // 通过 Sec-WebSocket-Protocol 协商的协议版本
+    protocol: ProtocolVersion,
+    // 限流：连接级令牌桶，用户级每秒消息数，窗口内最大超限次数
+    limiter: ConnectionLimiter,
+    user_rate: u32,
+    max_violations: u32,
+    // 用户级限流检查期间到达的数据帧，检查完成后按顺序处理
+    rate_queue: VecDeque<ClientData>,
+    rate_checking: bool,
+    // 正在接收的分片消息
+    fragments: Option<Fragments>,
+    // 连接级日志 span，携带 session_id / app_id / user_id
+    span: tracing::Span,
 }
 
 /// 全局会话管理器
@@ -72,9 +162,57 @@ impl SessionManager {
         self.sessions.lock().await.get(session_id).cloned()
     }
 
-    // 链接是否存在
-    pub async fn is_session_exists(&self, session_id: &str) -> bool {
-        return self.sessions.lock().await.contains_key(session_id);
+    // 本节点连接数
+    pub async fn count(&self) -> usize {
+        self.sessions.lock().await.len()
+    }
+
+    // 断开本节点上的指定连接
+    pub async fn disconnect(&self, session_id: &str, code: ws::CloseCode, reason: &str) -> bool {
+        let Some(addr) = self.get_session(session_id).await else {
+            return false;
+        };
+        addr.do_send(Disconnect {
+            code,
+            reason: reason.to_string(),
+        });
+        true
+    }
+
+    // 断开本节点全部连接
+    pub async fn disconnect_all(&self, code: ws::CloseCode, reason: &str) {
+        for addr in self.sessions.lock().await.values() {
+            addr.do_send(Disconnect {
+                code,
+                reason: reason.to_string(),
+            });
+        }
+    }
+
+    // 发送二进制消息到本节点的连接
+    pub(crate) async fn send_binary(&self, session_id: &str, data: Bytes) -> bool {
+        let Some(addr) = self.get_session(session_id).await else {
+            return false;
+        };
+        metrics::mailbox_enqueued(session_id);
+        addr.do_send(ServerBinary(data));
+        true
+    }
+
+    // 发送消息到本节点的连接，message_id 不为空时 message 为需要确认的信封
+    pub(crate) async fn send_message(&self, session_id: &str, message: &str, message_id: Option<&str>) -> bool {
+        let Some(addr) = self.get_session(session_id).await else {
+            return false;
+        };
+        metrics::mailbox_enqueued(session_id);
+        match message_id {
+            Some(id) => addr.do_send(ServerEnvelope {
+                id: id.to_string(),
+                envelope: message.to_string(),
+            }),
+            None => addr.do_send(ServerText(message.to_string())),
+        }
+        true
     }
 }
 pub async fn ws_handler(
@@ -82,271 +220,822 @@ pub async fn ws_handler(
     stream: web::Payload,
     state: Data<AppState>,
 ) -> Result<HttpResponse, Error> {
-    let query = web::Query::<WsQuery>::from_query(req.query_string()).unwrap();
+    // 排空模式下不再接受新连接，客户端重连到其他节点
+    if state.draining.load(Ordering::Relaxed) {
+        warn!("WebSocket connection rejected: node is draining");
+        return Err(AppError::Unavailable("Node is draining".to_string()).into());
+    }
+
+    let Ok(query) = web::Query::<WsQuery>::from_query(req.query_string()) else {
+        return Err(AppError::BadRequest("Invalid query string".to_string()).into());
+    };
 
     // 验证app_id和token是否传入
-    if query.app_id.is_none() || query.token.is_none() {
-        println!("WebSocket connection rejected: Missing app_id or token");
-        return Ok(HttpResponse::Forbidden().finish());
-    }
+    let (Some(app_id), Some(token)) = (&query.app_id, &query.token) else {
+        warn!("WebSocket connection rejected: Missing app_id or token");
+        metrics::AUTH_FAILURES_TOTAL.inc(&["missing_token"]);
+        return Err(AppError::Forbidden("Missing app_id or token".to_string()).into());
+    };
 
-    if let Some(ref token) = query.token {
-        println!("WebSocket connection with token: {}", token);
-    } else {
-        println!("WebSocket connection without token");
-    }
+    // 协商协议版本，客户端声明的子协议都不支持时拒绝升级
+    let protocol_header = req
+        .headers()
+        .get(header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|v| v.to_str().ok());
+    let Some(protocol) = ProtocolVersion::negotiate(protocol_header) else {
+        warn!("WebSocket connection rejected: unsupported protocol {:?}", protocol_header);
+        return Err(AppError::BadRequest("Unsupported Sec-WebSocket-Protocol".to_string()).into());
+    };
+
+    // 升级前完成认证，失败直接返回 401/403
+    let authorized = match auth::authenticate(&state.db, &state.redis, &state.http, &state.config.current(), app_id, token).await {
+        Ok(authorized) => authorized,
+        Err(e) => {
+            warn!("WebSocket connection rejected: {}", e);
+            metrics::AUTH_FAILURES_TOTAL.inc(&[e.reason()]);
+            return Err(AppError::from(e).into());
+        }
+    };
+    let app = authorized.app;
 
     // 生成唯一的session ID
     let session_id = Uuid::new_v4().to_string();
-    println!("New WebSocket connection, session ID: {}", session_id);
-
-    ws::start(
-        WsConn {
-            state,
-            token: query.token.clone(),
-            app_id: query.app_id.clone(),
-            session_id,
-            client_id: None,
-            user_id: query.user_id.clone(),
+    info!("New WebSocket connection {} for user {}", session_id, authorized.user_id);
+
+    let config = state.config.current();
+    // 不挂在 request span 下，连接生命周期长于升级请求
+    let span = tracing::info_span!(
+        parent: None,
+        "session",
+        session_id = %session_id,
+        app_id = %app.app_id,
+        user_id = %authorized.user_id
+    );
+
+    let messages_in_total = metrics::MESSAGES_IN_TOTAL.with(&[&app.app_id]);
+    let messages_out_total = metrics::MESSAGES_OUT_TOTAL.with(&[&app.app_id]);
+    let conn = WsConn {
+        state,
+        session_id,
+        user_id: authorized.user_id,
+        session_limit: SessionLimit {
+            max: app.max_sessions_per_user,
+            policy: app.session_policy(),
         },
-        &req,
-        stream,
-    )
+        app_id: app.app_id,
+        app_token: app.token,
+        callback_url: app.app_callback_message,
+        topics: HashSet::new(),
+        pending: HashMap::new(),
+        ack_timeout: Duration::from_secs(config.ack_timeout_secs),
+        ack_max_redeliveries: config.ack_max_redeliveries,
+        last_heartbeat: Instant::now(),
+        heartbeat_interval: Duration::from_secs(config.heartbeat_interval_secs),
+        idle_timeout: Duration::from_secs(config.client_idle_timeout_secs),
+        session_ttl: config.session_ttl_secs,
+        registered: Arc::new(AtomicBool::new(false)),
+        connected_at: now_millis(),
+        device: query.device.clone().filter(|d| !d.is_empty()),
+        remote_ip: req.connection_info().realip_remote_addr().map(str::to_string),
+        messages_in: 0,
+        messages_out: 0,
+        mailbox: Arc::new(AtomicI64::new(0)),
+        messages_in_total,
+        messages_out_total,
+        protocol,
+        limiter: ConnectionLimiter::new(
+            config.rate_conn_messages_per_sec,
+            config.rate_conn_bytes_per_sec,
+            MAX_MESSAGE_SIZE as u64,
+        ),
+        user_rate: config.rate_user_messages_per_sec,
+        max_violations: config.rate_max_violations,
+        rate_queue: VecDeque::new(),
+        rate_checking: false,
+        fragments: None,
+        span,
+    };
+    ws::WsResponseBuilder::new(conn, &req, stream)
+        .protocols(&SUPPORTED_PROTOCOLS)
+        .start()
 }
 
+/// 客户端指令
+/// - 订阅：{"action":"subscribe","topic":"lobby"}，取消订阅同理
+/// - 消息确认：{"action":"ack","id":"..."}
 #[derive(Deserialize, Debug)]
-#[allow(dead_code)]
-pub struct WsContext {
-    code: i32,               // 状态吗
-    message: Option<String>, // 错误信息
-    data: Option<String>,    // 数据
-    client_id: Option<String>,
+pub struct ClientCommand {
+    action: String,
+    #[serde(default)]
+    topic: Option<String>,
+    #[serde(default)]
+    id: Option<String>,
 }
 
-#[derive(Deserialize, Debug)]
-#[allow(dead_code)]
-pub struct PushRequest {
-    code: i32,                       // 状态吗
-    message: Option<String>,         // 错误信息
-    pub(crate) data: Option<String>, // 数据
-    pub(crate) client_id: Option<String>,
-    pub(crate) user_id: Option<String>,
-    pub(crate) app_id: Option<String>,
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ServerText(pub String);
+
+/// 以二进制帧发送给客户端
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ServerBinary(pub Bytes);
+
+impl Handler<ServerBinary> for WsConn {
+    type Result = ();
+
+    fn handle(&mut self, msg: ServerBinary, ctx: &mut Self::Context) {
+        let _span = self.span.clone().entered();
+        debug!("Sending {} bytes to client", msg.0.len());
+        self.message_sent();
+        ctx.binary(msg.0);
+    }
 }
 
+/// 需要客户端确认的消息，超时未确认会重发
 #[derive(Message)]
 #[rtype(result = "()")]
-pub struct ServerText(pub String);
+pub struct ServerEnvelope {
+    pub id: String,
+    pub envelope: String,
+}
+
+impl Handler<ServerEnvelope> for WsConn {
+    type Result = ();
+
+    fn handle(&mut self, msg: ServerEnvelope, ctx: &mut Self::Context) {
+        let _span = self.span.clone().entered();
+        // 同一消息已在等待确认，由已有的定时器负责重发
+        if self.pending.contains_key(&msg.id) {
+            debug!("Message {} already pending on session {}", msg.id, self.session_id);
+            self.mailbox.fetch_sub(1, Ordering::Relaxed);
+            return;
+        }
+        self.message_sent();
+        ctx.text(msg.envelope.clone());
+        // 超出上限的消息不在本地重发，仍保留在 Redis，重连后重发
+        if self.pending.len() >= MAX_PENDING_ACKS {
+            warn!("Too many pending messages on session {}, not redelivering {}", self.session_id, msg.id);
+            return;
+        }
+        self.pending.insert(msg.id.clone(), 0);
+        self.schedule_redelivery(msg.id, msg.envelope, ctx);
+    }
+}
 
 impl Handler<ServerText> for WsConn {
     type Result = ();
 
     fn handle(&mut self, msg: ServerText, ctx: &mut Self::Context) {
-        // 检查消息是否包含401错误码
-        if msg.0.contains("\"code\":401") || msg.0.contains("\"code\": 401") {
-            // 解析JSON消息以检查code字段
-            if let Ok(parsed_msg) = serde_json::from_str::<serde_json::Value>(&msg.0) {
-                if parsed_msg.get("code").and_then(|c| c.as_i64()) == Some(401) {
-                    // 如果code是401，发送错误消息并关闭连接
-                    ctx.close(None);
+        let _span = self.span.clone().entered();
+        debug!("Sending message to client: {}", msg.0);
+        self.message_sent();
+        ctx.text(msg.0);
+    }
+}
+
+/// 服务端主动断开连接，携带关闭码与原因
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Disconnect {
+    pub code: ws::CloseCode,
+    pub reason: String,
+}
+
+impl Handler<Disconnect> for WsConn {
+    type Result = ();
+
+    fn handle(&mut self, msg: Disconnect, ctx: &mut Self::Context) {
+        let _span = self.span.clone().entered();
+        info!("Disconnecting session {}: {}", self.session_id, msg.reason);
+        ctx.close(Some(ws::CloseReason {
+            code: msg.code,
+            description: Some(msg.reason),
+        }));
+        ctx.stop();
+    }
+}
+
+impl WsConn {
+    /// 注册到会话管理器并登记到 Redis：超出会话数时按策略拒绝或踢掉旧会话；离线消息在登记前投递，登记完成后删除
+    fn register_user_session(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        let addr = ctx.address();
+        let state = self.state.clone();
+        let app_id = self.app_id.clone();
+        let user_id = self.user_id.clone();
+        let session_id = self.session_id.clone();
+        let callback = PresenceCallback {
+            app_token: self.app_token.clone(),
+            callback_url: self.callback_url.clone(),
+        };
+        let limit = self.session_limit;
+        let config = self.state.config.current();
+        let node = self.app_node(&config);
+        let registered = self.registered.clone();
+
+        spawn(async move {
+            // 先注册到本节点，避免登记后推送过来时找不到连接
+            state.session_manager.add_session(session_id.clone(), addr.clone()).await;
+            let redis = &state.redis;
+
+            // 记录本节点写入的 key，停机或重启时清理
+            let redis_session_key = session_registry::session_key(&app_id, &user_id);
+            if let Err(e) = shutdown::track_user_key(redis, &node, &redis_session_key).await {
+                error!("Track session key failed: {:?}", e);
+            }
+
+            // 先投递未确认的消息与离线消息再登记，登记后到达的实时推送不会早于离线消息
+            // 离线消息在事务内锁定（其他会话跳过），登记完成后才删除并提交
+            Self::resend_pending(redis, &addr, &session_id, &app_id, &user_id).await;
+            let claim = Self::deliver_offline(redis, &state.db, &addr, &session_id, &app_id, &user_id, config.ack_pending_ttl).await;
+
+            let result = session_registry::register(
+                redis,
+                &app_id,
+                &user_id,
+                node,
+                config.session_ttl_secs,
+                &limit,
+            )
+            .await;
+            // 登记失败（Redis 不可用）也开始续期：续期发现记录不存在时断开，客户端重连后重新登记
+            registered.store(!matches!(result, Ok(Registration::Rejected)), Ordering::Relaxed);
+
+            let count = match result {
+                Ok(Registration::Registered { count, evicted }) => {
+                    Self::evict_sessions(&state, &config, &user_id, evicted).await;
+                    count
+                }
+                Ok(Registration::Rejected) => {
+                    // 事务随 claim 丢弃而回滚，离线消息保留到下次上线
+                    warn!("User {} reached max sessions, rejecting {}", user_id, session_id);
+                    addr.do_send(Disconnect {
+                        code: ws::CloseCode::Policy,
+                        reason: "too many sessions".to_string(),
+                    });
                     return;
                 }
+                Err(e) => {
+                    error!("Error registering session: {:?}", e);
+                    0
+                }
+            };
+            if let Some((tx, delivered)) = claim {
+                Self::complete_offline(tx, &delivered).await;
+            }
+            // 投递与登记之间推送的消息仍会存入离线消息，登记后再投递一次
+            if let Some((tx, delivered)) =
+                Self::deliver_offline(redis, &state.db, &addr, &session_id, &app_id, &user_id, config.ack_pending_ttl).await
+            {
+                Self::complete_offline(tx, &delivered).await;
+            }
+
+            // 第一个会话，用户上线
+            if count == 1 {
+                presence::notify(state, app_id, user_id, session_id, true, 1, callback);
+            }
+        });
+    }
+
+    /// 踢掉超出会话数上限的旧会话（可能在其他节点上）
+    async fn evict_sessions(state: &AppState, config: &Config, user_id: &str, evicted: Vec<AppNode>) {
+        for node in evicted {
+            info!("Evicting session {} of user {}", node.session_id, user_id);
+            let kicked = session_control::kick(
+                &state.redis,
+                &state.session_manager,
+                config,
+                &node,
+                session_control::CLOSE_LOGGED_IN_ELSEWHERE,
+                "logged in elsewhere",
+            )
+            .await;
+            match kicked {
+                Ok(true) => {}
+                Ok(false) => warn!("Evicted session {} not found on {}:{}", node.session_id, node.ip, node.port),
+                Err(e) => error!("Evict session {} failed: {:?}", node.session_id, e),
             }
-        }else{
-            debug!("Sending message to client: {}", msg.0);
-            ctx.text(msg.0);
         }
     }
-}
 
-impl WsConn {
-    /// 验证token并更新会话信息
-    fn validate_and_update_session(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
-        if let Some(token) = &self.token {
-            let addr = ctx.address();
-            let token_clone = token.clone();
-            let db = self.state.db.clone();
-            let redis = self.state.redis.clone();
-            let session_manager = self.state.session_manager.clone();
-            let app_id = self.app_id.clone();
-            let session_id = self.session_id.clone();
-            let config = get_config().expect("Failed to load config");
+    /// 邮箱中的一条下行消息已发给客户端
+    fn message_sent(&mut self) {
+        self.messages_out += 1;
+        self.mailbox.fetch_sub(1, Ordering::Relaxed);
+        self.messages_out_total.fetch_add(1, Ordering::Relaxed);
+    }
 
-            spawn(async move {
-                debug!("Validating token: {}", token_clone.as_str());
-
-                match get_app_id(&db, app_id.unwrap()).await {
-                    Ok(app_usr) => {
-                        debug!("Found app user: {:?}", app_usr);
-
-                        let token = token_clone;
-                        let app_token = app_usr.token;
-                        let data = format!(r#"{{"token":"{}","appToken":"{}"}}"#, token, app_token);
-
-                        match http_post(&app_usr.app_auth_url, &data, &[]).await {
-                            Ok(response) => {
-                                if let Some(code) = response.get("code").and_then(Value::as_i64) {
-                                    if code == 200 {
-                                        if let Some(user_id) = response
-                                            .get("data")
-                                            .and_then(|d| d.get("userId"))
-                                            .and_then(Value::as_str)
-                                        {
-                                            debug!("User ID: {}", user_id);
-
-                                            let node = AppNode::new(
-                                                config.app_ip,
-                                                config.port,
-                                                session_id.clone(),
-                                            );
-                                            let redis_session_key = format!(
-                                                "web:socket:app_id:{}:user:id:{}",
-                                                app_usr.app_id, user_id
-                                            );
-
-                                            match redis.get(&redis_session_key) {
-                                                Ok(cached_session) => {
-                                                    debug!("Successfully retrieved cached session");
-                                                    if let Err(e) = Self::update_existing_session(
-                                                        cached_session,
-                                                        node,
-                                                        &redis,
-                                                        &redis_session_key,
-                                                        &session_manager,
-                                                    )
-                                                    .await
-                                                    {
-                                                        error!(
-                                                            "Error updating existing session: {:?}",
-                                                            e
-                                                        );
-                                                    }
-                                                }
-                                                Err(_) => {
-                                                    debug!("Creating new session for user");
-                                                    if let Err(e) = Self::create_new_session(
-                                                        node,
-                                                        &redis,
-                                                        &redis_session_key,
-                                                    )
-                                                    .await
-                                                    {
-                                                        error!(
-                                                            "Error creating new session: {:?}",
-                                                            e
-                                                        );
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    } else {
-                                        // 验证失败，向连接发送关闭消息
-                                        addr.do_send(ServerText(
-                                            r#"{"code":401,"message":"Token validation failed"}"#
-                                                .to_string(),
-                                        ));
-                                    }
-                                } else {
-                                    // 验证失败，向连接发送关闭消息
-                                    addr.do_send(ServerText(r#"{"code":401,"message":"Invalid response from auth server"}"#.to_string()));
-                                }
-                            }
-                            Err(error) => {
+    /// 本连接在会话登记中的记录
+    fn app_node(&self, config: &Config) -> AppNode {
+        let mut node = AppNode::new(config.app_ip.clone(), config.port, self.session_id.clone());
+        node.connected_at = self.connected_at;
+        node.device = self.device.clone();
+        node.remote_ip = self.remote_ip.clone();
+        node.messages_in = self.messages_in;
+        node.messages_out = self.messages_out;
+        node
+    }
 
-                                error!("Auth server error: {:?}", error);
-                                addr.do_send(ServerText(
-                                    r#"{"code":401,"message":"Auth server error"}"#.to_string(),
-                                ));
-                            }
-                        };
+    /// 重发上次未确认的消息
+    async fn resend_pending(redis: &RedisManager, addr: &Addr<WsConn>, session_id: &str, app_id: &str, user_id: &str) {
+        match ack::load_pending(redis, app_id, user_id).await {
+            Ok(pending) => {
+                for message in pending {
+                    metrics::mailbox_enqueued(session_id);
+                    addr.do_send(ServerEnvelope {
+                        id: message.id,
+                        envelope: message.envelope,
+                    });
+                }
+            }
+            Err(e) => error!("Load pending messages failed: {:?}", e),
+        }
+    }
+
+    /// 在事务内锁定并按写入顺序投递离线消息，返回事务与已送达连接的消息ID
+    /// 连接已关闭时停止投递，未送达的消息在提交后仍保留到下次上线
+    async fn deliver_offline(
+        redis: &RedisManager,
+        db: &PgPool,
+        addr: &Addr<WsConn>,
+        session_id: &str,
+        app_id: &str,
+        user_id: &str,
+        pending_ttl: i64,
+    ) -> Option<(Transaction<'static, Postgres>, Vec<i64>)> {
+        let claimed = async {
+            let mut tx = db.begin().await?;
+            let messages = claim_offline_messages(&mut tx, app_id, user_id).await?;
+            Ok::<_, sqlx::Error>((tx, messages))
+        };
+        let (tx, messages) = match claimed.await {
+            Ok(claimed) => claimed,
+            Err(e) => {
+                error!("Load offline messages failed: {:?}", e);
+                return None;
+            }
+        };
+        debug!("Flushing {} offline messages to user {}", messages.len(), user_id);
+        let mut delivered = Vec::with_capacity(messages.len());
+        for message in messages {
+            let id = message.id;
+            let sent = if message.binary {
+                match base64_utils::decode(&message.message) {
+                    Ok(data) => {
+                        metrics::mailbox_enqueued(session_id);
+                        addr.send(ServerBinary(data)).await
                     }
-                    Err(_) => {
-                        error!("Application does not exist");
-                        addr.do_send(ServerText(
-                            r#"{"code":401,"message":"Invalid app_id"}"#.to_string(),
-                        ));
+                    Err(e) => {
+                        // 无法解码的消息不会再投递成功，直接删除
+                        error!("Decode offline binary message {} failed: {:?}", id, e);
+                        Ok(())
                     }
-                };
-                // return Ok(code);
-            });
+                }
+            } else {
+                metrics::mailbox_enqueued(session_id);
+                match message.message_id {
+                    Some(message_id) => {
+                        // 需要确认的消息重新登记，确认前断开可再次重发
+                        if let Err(e) = ack::store_pending(redis, app_id, user_id, &message_id, &message.message, pending_ttl).await {
+                            error!("Store pending message {} failed: {:?}", message_id, e);
+                        }
+                        addr.send(ServerEnvelope {
+                            id: message_id,
+                            envelope: message.message,
+                        })
+                        .await
+                    }
+                    None => addr.send(ServerText(message.message)).await,
+                }
+            };
+            if let Err(e) = sent {
+                warn!("Session {} closed while flushing offline messages: {}", session_id, e);
+                break;
+            }
+            delivered.push(id);
+        }
+        Some((tx, delivered))
+    }
+
+    /// 删除已投递的离线消息并提交，释放行锁；失败时回滚，消息保留到下次上线
+    async fn complete_offline(mut tx: Transaction<'static, Postgres>, delivered: &[i64]) {
+        if let Err(e) = delete_offline_messages(&mut tx, delivered).await {
+            error!("Delete delivered offline messages failed: {:?}", e);
+            return;
         }
+        if let Err(e) = tx.commit().await {
+            error!("Commit delivered offline messages failed: {:?}", e);
+        }
+    }
+
+    /// 将客户端消息投递到应用的回调地址，二进制消息以 base64 编码投递
+    fn deliver_upstream(&self, message: String, encoding: Option<&'static str>) {
+        let mut message = self.upstream_message(message);
+        message.encoding = encoding;
+        self.state.upstream.dispatch(self.callback_url.clone(), message);
+    }
+
+    fn upstream_message(&self, message: String) -> UpstreamMessage {
+        UpstreamMessage::new(
+            self.app_id.clone(),
+            self.app_token.clone(),
+            Some(self.user_id.clone()),
+            self.session_id.clone(),
+            message,
+        )
     }
 
-    /// 更新现有会话
-    async fn update_existing_session(
-        cached_session: String,
-        node: AppNode,
-        redis: &Data<RedisManager>,
-        redis_session_key: &str,
-        session_manager: &SessionManager,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        debug!("Updating existing session");
+    /// 处理客户端指令，返回 false 表示不是可识别的指令
+    fn handle_command(&mut self, command: ClientCommand, ctx: &mut ws::WebsocketContext<Self>) -> bool {
+        match (command.action.as_str(), command.topic, command.id) {
+            ("subscribe", Some(topic_name), _) => self.handle_topic_command(true, topic_name, None, ctx),
+            ("unsubscribe", Some(topic_name), _) => self.handle_topic_command(false, topic_name, None, ctx),
+            ("ack", _, Some(id)) => self.handle_ack(id),
+            _ => return false,
+        }
+        true
+    }
 
-        let mut session_user: SessionUser = serde_json::from_str(&cached_session)?;
+    /// 客户端数据帧：先检查连接级限流，再检查用户级限流（Redis，所有会话共用）
+    fn handle_data(&mut self, data: ClientData, ctx: &mut ws::WebsocketContext<Self>) {
+        self.messages_in += 1;
+        self.messages_in_total.fetch_add(1, Ordering::Relaxed);
+        if !self.limiter.allow(data.len()) {
+            self.reject_rate_limited("connection", ctx);
+            return;
+        }
+        if self.user_rate == 0 {
+            self.process_data(data, ctx);
+            return;
+        }
+        if self.rate_queue.len() >= MAX_QUEUED_FRAMES {
+            self.reject_rate_limited("user", ctx);
+            return;
+        }
+        // 排队等待检查，保证消息顺序；检查期间 actor 照常处理心跳与下行消息
+        self.rate_queue.push_back(data);
+        if !self.rate_checking {
+            self.check_user_rate(ctx);
+        }
+    }
 
-        // 清理不存在的节点
-        Self::cleanup_stale_nodes(&mut session_user, session_manager).await;
+    /// 依次检查队首数据帧的用户级限流（Redis），检查期间不阻塞 actor
+    fn check_user_rate(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+        // 超限断开后不再处理剩余的帧
+        let next = if ctx.state().alive() { self.rate_queue.pop_front() } else { None };
+        let Some(data) = next else {
+            self.rate_queue.clear();
+            self.rate_checking = false;
+            return;
+        };
+        self.rate_checking = true;
+        let key = rate_limit::user_rate_key(&self.app_id, &self.user_id);
+        let redis = self.state.redis.clone();
+        let rate = self.user_rate;
+        ctx.spawn(
+            async move { rate_limit::allow(&redis, &key, rate).await }
+                .into_actor(self)
+                .map(move |allowed, act, ctx| {
+                    let _span = act.span.clone().entered();
+                    match allowed {
+                        Ok(true) => act.process_data(data, ctx),
+                        Ok(false) => act.reject_rate_limited("user", ctx),
+                        Err(e) => {
+                            // Redis 不可用时放行，只依赖连接级限流
+                            warn!("Check user rate limit failed: {:?}", e);
+                            act.process_data(data, ctx);
+                        }
+                    }
+                    act.check_user_rate(ctx);
+                }),
+        );
+    }
 
-        // 添加当前节点
-        session_user.nodes.push(node);
+    /// 分片消息：按顺序拼接，收到最后一片后作为完整的数据帧处理
+    /// 分片顺序错误、超过 MAX_MESSAGE_SIZE 或文本不是 UTF-8 时关闭连接
+    fn handle_fragment(&mut self, item: Item, ctx: &mut ws::WebsocketContext<Self>) {
+        let (chunk, last) = match item {
+            Item::FirstText(_) | Item::FirstBinary(_) if self.fragments.is_some() => {
+                self.close_invalid(ws::CloseCode::Protocol, "unexpected first fragment", ctx);
+                return;
+            }
+            Item::FirstText(data) => {
+                self.fragments = Some(Fragments { binary: false, data: BytesMut::new() });
+                (data, false)
+            }
+            Item::FirstBinary(data) => {
+                self.fragments = Some(Fragments { binary: true, data: BytesMut::new() });
+                (data, false)
+            }
+            Item::Continue(data) => (data, false),
+            Item::Last(data) => (data, true),
+        };
+        let Some(fragments) = self.fragments.as_mut() else {
+            self.close_invalid(ws::CloseCode::Protocol, "continuation without first fragment", ctx);
+            return;
+        };
+        if fragments.data.len() + chunk.len() > MAX_MESSAGE_SIZE {
+            self.fragments = None;
+            self.close_invalid(ws::CloseCode::Size, "message too large", ctx);
+            return;
+        }
+        fragments.data.extend_from_slice(&chunk);
+        if !last {
+            return;
+        }
 
-        // 保存更新后的会话信息
-        let updated_session = serde_json::to_string(&session_user)?;
-        redis.set(redis_session_key, &updated_session)?;
+        let Some(Fragments { binary, data }) = self.fragments.take() else { return };
+        if binary {
+            self.handle_data(ClientData::Binary(data.freeze()), ctx);
+            return;
+        }
+        match String::from_utf8(data.to_vec()) {
+            Ok(text) => self.handle_data(ClientData::Text(text), ctx),
+            Err(_) => self.close_invalid(ws::CloseCode::Invalid, "invalid utf-8 text", ctx),
+        }
+    }
 
-        debug!("Session updated successfully");
-        Ok(())
+    // 客户端违反协议：关闭连接
+    fn close_invalid(&mut self, code: ws::CloseCode, reason: &str, ctx: &mut ws::WebsocketContext<Self>) {
+        warn!("Closing session {}: {}", self.session_id, reason);
+        ctx.close(Some(ws::CloseReason {
+            code,
+            description: Some(reason.to_string()),
+        }));
+        ctx.stop();
     }
 
-    /// 清理失效的节点
-    async fn cleanup_stale_nodes(session_user: &mut SessionUser, session_manager: &SessionManager) {
-        let config = get_config().expect("Failed to load config");
+    /// 超限：回复错误，窗口内超限次数过多则断开连接
+    fn reject_rate_limited(&mut self, scope: &str, ctx: &mut ws::WebsocketContext<Self>) {
+        let violations = self.limiter.record_violation();
+        warn!(
+            "Session {} exceeded {} rate limit ({} times in window)",
+            self.session_id, scope, violations
+        );
+        if self.max_violations > 0 && violations >= self.max_violations {
+            ctx.close(Some(ws::CloseReason {
+                code: ws::CloseCode::Policy,
+                description: Some("rate limit exceeded".to_string()),
+            }));
+            ctx.stop();
+            return;
+        }
 
-        let mut nodes_to_remove = Vec::new();
+        let reply = match self.protocol {
+            ProtocolVersion::V1 => {
+                let message = format!("{} rate limit exceeded", scope);
+                ServerFrame::error(None, ErrorCode::RateLimited, message).to_json()
+            }
+            ProtocolVersion::Legacy => {
+                json!({"code": 429, "message": "Rate limit exceeded", "scope": scope}).to_string()
+            }
+        };
+        ctx.text(reply);
+    }
 
-        // 收集需要删除的节点索引
-        for (index, app_node) in session_user.nodes.iter().enumerate() {
-            if app_node.ip == config.app_ip && config.port == app_node.port {
-                // 在同一节点上，检查会话是否存在
-                if !session_manager
-                    .is_session_exists(&app_node.session_id)
-                    .await
-                {
-                    nodes_to_remove.push(index);
-                }
+    fn process_data(&mut self, data: ClientData, ctx: &mut ws::WebsocketContext<Self>) {
+        match data {
+            ClientData::Text(text) if self.protocol == ProtocolVersion::V1 => {
+                debug!("Received frame: {}", text);
+                self.handle_frame(&text, ctx);
+            }
+            ClientData::Text(text) => self.handle_text(text, ctx),
+            ClientData::Binary(data) => {
+                debug!("Received {} bytes from session {}", data.len(), self.session_id);
+                self.deliver_upstream(base64_utils::encode(&data), Some("base64"));
             }
         }
+    }
 
-        // 逆序删除节点，避免索引变化问题
-        for &index in nodes_to_remove.iter().rev() {
-            session_user.nodes.remove(index);
+    /// 处理旧协议的文本消息
+    fn handle_text(&mut self, text: String, ctx: &mut ws::WebsocketContext<Self>) {
+        debug!("Received message: {}", text);
+        // 客户端指令不投递到应用
+        if let Ok(command) = serde_json::from_str::<ClientCommand>(&text)
+            && self.handle_command(command, ctx)
+        {
+            return;
         }
+        // 所有客户端消息都投递到应用回调地址
+        self.deliver_upstream(text, None);
     }
 
-    /// 创建新会话
-    async fn create_new_session(
-        node: AppNode,
-        redis: &Data<RedisManager>,
-        redis_session_key: &str,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        debug!("Creating new session");
+    /// 处理 v1 协议的客户端帧，无法解析的帧回复错误
+    fn handle_frame(&mut self, text: &str, ctx: &mut ws::WebsocketContext<Self>) {
+        let frame = match protocol::parse(text) {
+            Ok(frame) => frame,
+            Err(reply) => {
+                ctx.text(reply.to_json());
+                return;
+            }
+        };
+        match frame {
+            ClientFrame::Subscribe { id, payload } => self.handle_topic_command(true, payload.topic, id, ctx),
+            ClientFrame::Unsubscribe { id, payload } => self.handle_topic_command(false, payload.topic, id, ctx),
+            ClientFrame::Publish { id, payload } => {
+                self.deliver_upstream(protocol::payload_text(payload), None);
+                ctx.text(ServerFrame::Reply { id, payload: Value::Null }.to_json());
+            }
+            ClientFrame::Ack { id } => self.handle_ack(id),
+            ClientFrame::Ping { id } => ctx.text(ServerFrame::Pong { id }.to_json()),
+            ClientFrame::Rpc { id, payload } => self.handle_rpc(id, payload, ctx),
+        }
+    }
 
-        let session_user = SessionUser::new(vec![node]);
-        let session_user_str = serde_json::to_string(&session_user)?;
-        redis.set(redis_session_key, &session_user_str)?;
+    /// rpc：同步调用应用回调地址，将应答回复给客户端
+    fn handle_rpc(&self, id: String, payload: Value, ctx: &mut ws::WebsocketContext<Self>) {
+        let mut message = self.upstream_message(protocol::payload_text(payload));
+        message.event = "rpc";
+        message.request_id = Some(id.clone());
 
-        debug!("New session created successfully");
-        Ok(())
+        let upstream = self.state.upstream.clone();
+        let callback_url = self.callback_url.clone();
+        let session_id = self.session_id.clone();
+        let addr = ctx.address();
+        spawn(async move {
+            let reply = match upstream.call(&callback_url, message).await {
+                Ok(response) => ServerFrame::Reply { id: Some(id), payload: response },
+                Err(e) => {
+                    warn!("Rpc {} to {} failed: {}", id, callback_url, e);
+                    ServerFrame::error(Some(id), ErrorCode::RpcFailed, "Rpc call failed")
+                }
+            };
+            metrics::mailbox_enqueued(&session_id);
+            addr.do_send(ServerText(reply.to_json()));
+        });
     }
 
-    /// 注册会话到管理器
-    fn register_session(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+    /// 处理主题订阅/取消订阅，v1 协议按请求ID回复
+    fn handle_topic_command(
+        &mut self,
+        subscribe: bool,
+        topic_name: String,
+        request_id: Option<String>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        let app_id = self.app_id.clone();
+        let user_id = self.user_id.clone();
+        let protocol = self.protocol;
+        if !topic::is_valid_topic(&topic_name) {
+            let reply = match protocol {
+                ProtocolVersion::V1 => ServerFrame::error(request_id, ErrorCode::InvalidTopic, "Invalid topic").to_json(),
+                ProtocolVersion::Legacy => json!({"code": 400, "message": "Invalid topic", "topic": topic_name}).to_string(),
+            };
+            ctx.text(reply);
+            return;
+        }
+
+        if subscribe {
+            self.topics.insert(topic_name.clone());
+        } else {
+            self.topics.remove(&topic_name);
+        }
+
+        let config = self.state.config.current();
+        let node = AppNode::new(config.app_ip.clone(), config.port, self.session_id.clone());
+        let redis = self.state.redis.clone();
         let addr = ctx.address();
-        let session_id = self.session_id.clone();
-        let manager = self.state.session_manager.clone();
+        let action = if subscribe { "subscribe" } else { "unsubscribe" };
+
+        spawn(async move {
+            // 上下线主题只能订阅自己或允许列表中的用户
+            if subscribe
+                && let Some(target) = presence::presence_target(&topic_name)
+                && !presence::can_watch(&redis, &app_id, &user_id, target).await.unwrap_or_else(|e| {
+                    error!("Check presence watcher failed: {:?}", e);
+                    false
+                })
+            {
+                warn!("User {} not allowed to subscribe {}", user_id, topic_name);
+                let reply = match protocol {
+                    ProtocolVersion::V1 => {
+                        ServerFrame::error(request_id, ErrorCode::Forbidden, "Not allowed to subscribe topic").to_json()
+                    }
+                    ProtocolVersion::Legacy => {
+                        json!({"code": 403, "message": "Not allowed to subscribe topic", "topic": topic_name}).to_string()
+                    }
+                };
+                metrics::mailbox_enqueued(&node.session_id);
+                addr.do_send(ServerText(reply));
+                return;
+            }
+            let result = if subscribe {
+                topic::subscribe(&redis, &app_id, &topic_name, &node).await
+            } else {
+                topic::unsubscribe(&redis, &app_id, &topic_name, &node).await
+            };
+            if let Err(e) = &result {
+                error!("Topic {} {} failed: {:?}", topic_name, action, e);
+            }
+            let reply = match (protocol, result) {
+                (ProtocolVersion::V1, Ok(_)) => ServerFrame::Reply {
+                    id: request_id,
+                    payload: json!({"action": action, "topic": topic_name}),
+                }
+                .to_json(),
+                (ProtocolVersion::V1, Err(_)) => {
+                    ServerFrame::error(request_id, ErrorCode::Internal, "Topic subscription failed").to_json()
+                }
+                (ProtocolVersion::Legacy, Ok(_)) => {
+                    json!({"code": 200, "message": action, "topic": topic_name}).to_string()
+                }
+                (ProtocolVersion::Legacy, Err(_)) => {
+                    json!({"code": 500, "message": "Topic subscription failed", "topic": topic_name}).to_string()
+                }
+            };
+            metrics::mailbox_enqueued(&node.session_id);
+            addr.do_send(ServerText(reply));
+        });
+    }
 
+    /// 客户端确认消息：停止本地重发并删除 Redis 中的未确认记录
+    fn handle_ack(&mut self, id: String) {
+        self.pending.remove(&id);
+        let app_id = self.app_id.clone();
+        let user_id = self.user_id.clone();
+        let redis = self.state.redis.clone();
         spawn(async move {
-            manager.add_session(session_id, addr).await;
+            if let Err(e) = ack::remove_pending(&redis, &app_id, &user_id, &id).await {
+                warn!("Remove pending message {} failed: {:?}", id, e);
+            }
+        });
+    }
+
+    /// 超时未确认则重发，超过最大次数后停止（消息仍保留在 Redis，重连后重发）
+    fn schedule_redelivery(&self, id: String, envelope: String, ctx: &mut ws::WebsocketContext<Self>) {
+        ctx.run_later(self.ack_timeout, move |act, ctx| {
+            let _span = act.span.clone().entered();
+            let Some(attempts) = act.pending.get_mut(&id) else {
+                return;
+            };
+            if *attempts >= act.ack_max_redeliveries {
+                warn!("Message {} not acked by session {}, giving up", id, act.session_id);
+                act.pending.remove(&id);
+                return;
+            }
+            *attempts += 1;
+            debug!("Redelivering message {} to session {}", id, act.session_id);
+            ctx.text(envelope.clone());
+            act.schedule_redelivery(id, envelope, ctx);
+        });
+    }
+
+    /// 定时发送 ping，超过空闲时间未收到客户端任何帧则关闭连接
+    /// 关闭后由 stopping 清理本地会话与 Redis 记录
+    fn start_heartbeat(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        ctx.run_interval(self.heartbeat_interval, |act, ctx| {
+            let _span = act.span.clone().entered();
+            if act.last_heartbeat.elapsed() > act.idle_timeout {
+                warn!("Session {} idle timeout, disconnecting", act.session_id);
+                ctx.close(Some(ws::CloseReason {
+                    code: ws::CloseCode::Away,
+                    description: Some("idle timeout".to_string()),
+                }));
+                ctx.stop();
+                return;
+            }
+            ctx.ping(b"");
+        });
+    }
+
+    /// 定时续期 Redis 中的会话登记，节点崩溃后记录随 TTL 过期
+    /// 首次登记完成后，记录已不存在（被踢掉的通知丢失或已被清理）时断开连接，让客户端重连后重新登记
+    fn start_session_refresh(&self, ctx: &mut ws::WebsocketContext<Self>) {
+        let interval = Duration::from_secs((self.session_ttl / 3).max(1));
+        ctx.run_interval(interval, |act, ctx| {
+            if !act.registered.load(Ordering::Relaxed) {
+                return;
+            }
+            let _span = act.span.clone().entered();
+            let app_id = act.app_id.clone();
+            let user_id = act.user_id.clone();
+            let config = act.state.config.current();
+            let node = act.app_node(&config);
+            let redis = act.state.redis.clone();
+            let ttl = act.session_ttl;
+            let addr = ctx.address();
+            let topic_keys: Vec<String> = act.topics.iter().map(|t| topic::topic_key(&app_id, t)).collect();
+            spawn(async move {
+                match session_registry::refresh(&redis, &app_id, &user_id, node.clone(), ttl).await {
+                    // 刷新本节点的 key 记录，未刷新的由节点心跳清理
+                    Ok(true) => {
+                        let session_key = session_registry::session_key(&app_id, &user_id);
+                        if let Err(e) = shutdown::track_user_key(&redis, &node, &session_key).await {
+                            warn!("Track session key failed: {:?}", e);
+                        }
+                        for key in topic_keys {
+                            if let Err(e) = shutdown::track_topic_key(&redis, &node, &key).await {
+                                warn!("Track topic key failed: {:?}", e);
+                            }
+                        }
+                    }
+                    Ok(false) => addr.do_send(Disconnect {
+                        code: ws::CloseCode::Restart,
+                        reason: "session no longer registered".to_string(),
+                    }),
+                    Err(e) => warn!("Refresh session of user {} failed: {:?}", user_id, e),
+                }
+            });
         });
     }
 }
@@ -355,56 +1044,64 @@ impl Actor for WsConn {
     type Context = ws::WebsocketContext<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
+        let _span = self.span.clone().entered();
         info!("Session {} started", self.session_id);
-        // app_id 与 token 没有传入不让连接
-        if self.app_id.is_none() || self.token.is_none() || self.user_id.is_none() {
-            warn!("WebSocket connection closed: Missing app_id or token");
-            ctx.close(None);
-            return;
-        }
+        self.mailbox = metrics::mailbox_register(&self.session_id);
+        metrics::CONNECTS_TOTAL.inc(&[&self.app_id]);
+        metrics::CONNECTIONS_ACTIVE.inc(&[&self.app_id]);
 
-        // 验证 token 并更新 Redis 会话信息
-        self.validate_and_update_session(ctx);
-        self.register_session(ctx);
+        // 认证已在升级前完成，登记会话
+        self.register_user_session(ctx);
+        self.start_heartbeat(ctx);
+        self.start_session_refresh(ctx);
     }
 
     fn stopping(&mut self, _ctx: &mut Self::Context) -> Running {
+        let _span = self.span.clone().entered();
         info!("Session {} stopping", self.session_id);
+        metrics::mailbox_unregister(&self.session_id);
+        metrics::DISCONNECTS_TOTAL.inc(&[&self.app_id]);
+        metrics::CONNECTIONS_ACTIVE.dec(&[&self.app_id]);
 
         // self.session_id 移除 redis
-
+        let state = self.state.clone();
         let app_id = self.app_id.clone();
         let user_id = self.user_id.clone();
-        let redis = self.state.redis.clone();
+        let session_id = self.session_id.clone();
+        let callback = PresenceCallback {
+            app_token: self.app_token.clone(),
+            callback_url: self.callback_url.clone(),
+        };
+        spawn(async move {
+            match session_registry::unregister(&state.redis, &app_id, &user_id, &session_id).await {
+                // 最后一个会话断开，用户下线
+                Ok(0) => presence::notify(state, app_id, user_id, session_id, false, 0, callback),
+                Ok(_) => {}
+                Err(e) => error!("Remove session {} from redis failed: {:?}", session_id, e),
+            }
+        });
 
-        // let redis = self.state.redis.clone();
-        // redis.get()
-        //
-        let redis_session_key = format!(
-            "web:socket:app_id:{}:user:id:{}",
-            &app_id.unwrap(),
-            &user_id.unwrap()
-        );
-        match redis.get(&redis_session_key) {
-            Ok(cached_session) => {
-                let mut session_user: SessionUser = serde_json::from_str(&cached_session).unwrap();
-                for (index, app_node) in session_user.nodes.iter().enumerate() {
-                    if app_node.session_id == self.session_id {
-                        session_user.nodes.remove(index);
-                        break;
+        // 清理主题订阅
+        if !self.topics.is_empty() {
+            let config = self.state.config.current();
+            let node = AppNode::new(config.app_ip.clone(), config.port, self.session_id.clone());
+            let topics: Vec<String> = self.topics.drain().collect();
+            let app_id = self.app_id.clone();
+            let redis = self.state.redis.clone();
+            spawn(async move {
+                for topic_name in topics {
+                    if let Err(e) = topic::unsubscribe(&redis, &app_id, &topic_name, &node).await {
+                        warn!("Unsubscribe topic {} failed: {:?}", topic_name, e);
                     }
                 }
-                let updated_session = serde_json::to_string(&session_user).unwrap();
-                redis.set(&redis_session_key, &updated_session).unwrap();
-            }
-            Err(error) => {}
+            });
         }
 
         // 从会话管理器中移除会话
         let session_manager = self.state.session_manager.clone();
         let session_id = self.session_id.clone();
 
-        tokio::spawn(async move {
+        spawn(async move {
             session_manager.remove_session(&session_id).await;
         });
 
@@ -414,56 +1111,24 @@ impl Actor for WsConn {
 
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsConn {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        let _span = self.span.clone().entered();
+        // 收到任何帧都视为客户端存活
+        if msg.is_ok() {
+            self.last_heartbeat = Instant::now();
+        }
         match msg {
-            Ok(ws::Message::Text(text)) => {
-                debug!("Received message: {}", text);
-                // 尝试解析为WsContext
-                if let Ok(ws_context) = serde_json::from_str::<WsContext>(&text) {
-                    debug!("Received message: {:?}", ws_context);
-
-                    // 检查是否是认证失败的消息
-                    if ws_context.code == 401 {
-                        if let Some(ref message) = ws_context.message {
-                            warn!("Authentication failed: {}", message);
-                            ctx.close(None); // 关闭连接
-                            return;
-                        }
-                        ctx.close(None); // 关闭连接
-                        return;
-                    }
-
-                    // 获取 sessionId
-                    debug!("My session id = {}", self.session_id);
-
-                    // 根据消息中的client_id发送给指定用户
-                    if let Some(target_client_id) = ws_context.client_id {
-                        // 在实际应用中，这里应该查找目标用户的连接并发送消息
-                        debug!("Sending message to specific client: {}", target_client_id);
-                        // 暂时将消息发回给自己，实际应用中应发送给target_client_id对应的客户端
-                        // ctx.text(text);
-                        let manager = self.state.session_manager.clone();
-                        let msg = text.clone();
-
-                        spawn(async move {
-                            if let Some(addr) = manager.get_session(&*target_client_id).await {
-                                addr.do_send(ServerText(msg.parse().unwrap()));
-                            }
-                        });
-                    } else {
-                        // 如果没有指定接收者，则将消息广播给所有连接的客户端
-                        debug!("Broadcasting message to all clients");
-                        ctx.text(text);
-                    }
-                } else {
-                    // 无法解析为WsContext的消息，直接广播
-                    debug!("Received non-WsContext message: {}", text);
-                    ctx.text(text);
-                }
+            Ok(ws::Message::Ping(payload)) => {
+                ctx.pong(&payload);
             }
+            Ok(ws::Message::Pong(_)) => {}
+            Ok(ws::Message::Text(text)) => self.handle_data(ClientData::Text(text.to_string()), ctx),
+            Ok(ws::Message::Binary(data)) => self.handle_data(ClientData::Binary(data), ctx),
             Ok(ws::Message::Close(_)) => {
                 info!("Client {} closed connection", self.session_id);
                 ctx.stop();
             }
+            Ok(ws::Message::Continuation(item)) => self.handle_fragment(item, ctx),
+            Ok(ws::Message::Nop) => {}
             _ => {
                 // 处理其他类型的消息
                 ctx.stop();
@@ -471,3 +1136,8 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsConn {
         }
     }
 }
+
+// 后台任务沿用当前 span，日志仍带上连接的 session_id 等字段
+fn spawn<F: Future<Output = ()> + 'static>(fut: F) {
+    actix::spawn(fut.in_current_span());
+}